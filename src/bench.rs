@@ -0,0 +1,149 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDate;
+
+use crate::core::column::Column;
+use crate::core::data::{DataType, DataValue};
+use crate::core::dates::DatePart;
+use crate::core::workbook::{SheetDiagnostics, Worksheet};
+use crate::query::engine::{QueryResult, run_query_with_threads};
+use crate::query::model::{Aggregation, DateGrouping, Dimension, Measure, NumericBins, QuerySpec};
+
+const DEFAULT_SIZES: [usize; 3] = [100_000, 500_000, 1_000_000];
+const REGIONS: [&str; 5] = ["North", "South", "East", "West", "Central"];
+
+/// Times grouped queries over generated sheets, serially and on every
+/// core, and checks that both give the same result. Runs `vizualizer
+/// --bench [rows]`; without a row count it tries a few sizes.
+pub fn run(rows: Option<usize>) {
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    let sizes = rows.map_or(DEFAULT_SIZES.to_vec(), |rows| vec![rows]);
+    println!("{:<16} {:>9} {:>11} {:>14} {:>8}  same result", "query", "rows", "serial", format!("{threads} threads"), "speedup");
+    for size in sizes {
+        let sheet = generate_sheet(size);
+        for (name, spec) in queries() {
+            let (serial_time, serial) = timed(&sheet, &spec, 1);
+            let (parallel_time, parallel) = timed(&sheet, &spec, threads);
+            println!(
+                "{name:<16} {size:>9} {:>8.1} ms {:>11.1} ms {:>7.2}x  {}",
+                millis(serial_time),
+                millis(parallel_time),
+                serial_time.as_secs_f64() / parallel_time.as_secs_f64().max(f64::EPSILON),
+                if serial.rows == parallel.rows { "yes" } else { "NO" },
+            );
+        }
+    }
+}
+
+fn timed(sheet: &Worksheet, spec: &QuerySpec, threads: usize) -> (Duration, QueryResult) {
+    let start = Instant::now();
+    let result = run_query_with_threads(sheet, spec, &|_| true, threads).unwrap_or_else(QueryResult::empty);
+    (start.elapsed(), result)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn queries() -> Vec<(&'static str, QuerySpec)> {
+    let spec = |rows: Vec<Dimension>, columns: Vec<Measure>| QuerySpec {
+        rows,
+        columns,
+        ..QuerySpec::empty()
+    };
+    let measure = |column: &str, aggregation| Measure::new(column.to_string(), aggregation);
+    vec![
+        (
+            "few groups",
+            spec(
+                vec![Dimension::new("Region".to_string())],
+                vec![
+                    measure("Amount", Aggregation::Sum),
+                    measure("Amount", Aggregation::Avg),
+                    measure("Quantity", Aggregation::Count),
+                ],
+            ),
+        ),
+        (
+            "many groups",
+            spec(
+                vec![Dimension::new("Customer".to_string())],
+                vec![measure("Amount", Aggregation::Sum), measure("Quantity", Aggregation::Max)],
+            ),
+        ),
+        (
+            "month by region",
+            spec(
+                vec![
+                    Dimension::new("Region".to_string()),
+                    Dimension {
+                        date: Some(DateGrouping::Part(DatePart::Month)),
+                        ..Dimension::new("Date".to_string())
+                    },
+                ],
+                vec![
+                    measure("Amount", Aggregation::Median),
+                    measure("Customer", Aggregation::CountDistinct),
+                ],
+            ),
+        ),
+        (
+            "binned amounts",
+            spec(
+                vec![Dimension {
+                    bins: Some(NumericBins::Width(50.0)),
+                    ..Dimension::new("Amount".to_string())
+                }],
+                vec![
+                    measure("Quantity", Aggregation::Count),
+                    measure("Amount", Aggregation::StdDev),
+                ],
+            ),
+        ),
+    ]
+}
+
+/// A sales-like sheet: a low-cardinality region, a customer for roughly
+/// every ten rows, three years of dates, and amounts with a few blanks.
+fn generate_sheet(rows: usize) -> Worksheet {
+    let mut random = XorShift(0x9e37_79b9_7f4a_7c15);
+    let start = NaiveDate::from_ymd_opt(2022, 1, 1).expect("valid date");
+    let customers = (rows / 10).max(1) as u64;
+    let (mut region, mut customer, mut date, mut amount, mut quantity) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for row in 0..rows {
+        region.push(DataValue::String(REGIONS[random.below(REGIONS.len() as u64) as usize].to_string()));
+        customer.push(DataValue::String(format!("Customer {}", random.below(customers))));
+        date.push(DataValue::Date(start + chrono::Duration::days(random.below(3 * 365) as i64)));
+        amount.push(match row % 101 {
+            0 => DataValue::Empty,
+            _ => DataValue::Number(random.below(100_000) as f64 / 100.0),
+        });
+        quantity.push(DataValue::Number((1 + random.below(20)) as f64));
+    }
+    Worksheet {
+        name: "Generated".to_string(),
+        columns: vec![
+            Column::new("Region".to_string(), DataType::String, region),
+            Column::new("Customer".to_string(), DataType::String, customer),
+            Column::new("Date".to_string(), DataType::Date, date),
+            Column::new("Amount".to_string(), DataType::Number, amount),
+            Column::new("Quantity".to_string(), DataType::Number, quantity),
+        ],
+        row_count: rows,
+        diagnostics: SheetDiagnostics::default(),
+    }
+}
+
+/// A small deterministic generator, so every run sees the same data.
+struct XorShift(u64);
+
+impl XorShift {
+    fn below(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+}
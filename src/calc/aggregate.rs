@@ -0,0 +1,254 @@
+use crate::calc::eval::{check, eval};
+use crate::calc::field::CalculatedField;
+use crate::calc::parser::{parse, CalcError, Expr};
+use crate::core::column::Column;
+use crate::core::data::{DataType, DataValue};
+use crate::query::model::Aggregation;
+
+/// A row-level expression aggregated over each group, such as the
+/// `[Profit]` in `SUM([Profit])`.
+#[derive(Debug, Clone)]
+pub struct AggregateSlot {
+    /// Bound to the indices of the sheet's columns.
+    pub expr: Expr,
+    pub aggregation: Aggregation,
+}
+
+/// A formula over aggregates, e.g. `SUM([Profit]) / SUM([Sales])`, evaluated
+/// once per group after aggregation.
+#[derive(Debug, Clone)]
+pub struct AggregateCalc {
+    /// The outer formula, with each aggregate call replaced by a field bound
+    /// to the matching slot index.
+    pub expr: Expr,
+    pub slots: Vec<AggregateSlot>,
+    pub data_type: DataType,
+}
+
+impl AggregateCalc {
+    pub fn compile(field: &CalculatedField, columns: &[Column]) -> Result<Self, CalcError> {
+        if field.name.trim().is_empty() {
+            return Err(CalcError::new("Aggregate calculation needs a name"));
+        }
+        let schema = columns
+            .iter()
+            .map(|column| (column.name.as_str(), column.data_type.clone()))
+            .collect::<Vec<_>>();
+
+        let mut slots = Vec::new();
+        let mut slot_types = Vec::new();
+        let mut expr = extract_slots(parse(&field.formula)?, &schema, &mut slots, &mut slot_types)?;
+        if slots.is_empty() {
+            return Err(CalcError::new(
+                "Aggregate calculations need at least one aggregate such as SUM([Sales])",
+            ));
+        }
+
+        let slot_names = (0..slots.len()).map(slot_name).collect::<Vec<_>>();
+        let slot_schema = slot_names
+            .iter()
+            .zip(slot_types)
+            .map(|(name, data_type)| (name.as_str(), data_type))
+            .collect::<Vec<_>>();
+        let data_type = check(&mut expr, &slot_schema)?;
+        Ok(Self {
+            expr,
+            slots,
+            data_type,
+        })
+    }
+
+    /// Evaluates the outer formula from the aggregated value of each slot.
+    pub fn evaluate(&self, slot_values: &[DataValue]) -> DataValue {
+        eval(&self.expr, &|index| {
+            slot_values.get(index).cloned().unwrap_or(DataValue::Empty)
+        })
+    }
+}
+
+fn slot_name(index: usize) -> String {
+    format!("#{index}")
+}
+
+/// Maps formula function names onto aggregations. `PERCENTILE` takes its
+/// percentile as a second, literal argument and is handled separately.
+fn aggregation_for(name: &str) -> Option<Aggregation> {
+    match name {
+        "SUM" => Some(Aggregation::Sum),
+        "AVG" => Some(Aggregation::Avg),
+        "COUNT" => Some(Aggregation::Count),
+        "COUNTD" => Some(Aggregation::CountDistinct),
+        "MIN" => Some(Aggregation::Min),
+        "MAX" => Some(Aggregation::Max),
+        "MEDIAN" => Some(Aggregation::Median),
+        "STDEV" => Some(Aggregation::StdDev),
+        "STDEVP" => Some(Aggregation::StdDevPop),
+        "VAR" => Some(Aggregation::Variance),
+        "VARP" => Some(Aggregation::VariancePop),
+        "FIRST" => Some(Aggregation::First),
+        "LAST" => Some(Aggregation::Last),
+        "MODE" => Some(Aggregation::Mode),
+        _ => None,
+    }
+}
+
+/// Replaces every aggregate call with a reference to a new slot, checking
+/// the aggregated row-level expression against the sheet on the way.
+fn extract_slots(
+    expr: Expr,
+    schema: &[(&str, DataType)],
+    slots: &mut Vec<AggregateSlot>,
+    slot_types: &mut Vec<DataType>,
+) -> Result<Expr, CalcError> {
+    let mut recurse = |expr: Expr| extract_slots(expr, schema, slots, slot_types);
+    match expr {
+        Expr::Call(name, args) if name == "PERCENTILE" || aggregation_for(&name).is_some() => {
+            let aggregation = match (aggregation_for(&name), args.get(1)) {
+                (Some(aggregation), None) if args.len() == 1 => aggregation,
+                (None, Some(Expr::Literal(DataValue::Number(p)))) if args.len() == 2 => {
+                    if p.fract() != 0.0 || !(1.0..=99.0).contains(p) {
+                        return Err(CalcError::new(
+                            "PERCENTILE expects a whole percentile from 1 to 99, e.g. PERCENTILE([Sales], 90)",
+                        ));
+                    }
+                    Aggregation::Percentile(*p as u8)
+                }
+                (None, _) => {
+                    return Err(CalcError::new(
+                        "PERCENTILE expects an expression and a literal percentile, e.g. PERCENTILE([Sales], 90)",
+                    ));
+                }
+                _ => {
+                    return Err(CalcError::new(format!("{name} expects exactly one argument")));
+                }
+            };
+            let mut inner = args.into_iter().next().unwrap_or(Expr::Literal(DataValue::Empty));
+            if contains_aggregate(&inner) {
+                return Err(CalcError::new(format!("{name} cannot contain another aggregate")));
+            }
+            let inner_type = check(&mut inner, schema)?;
+            let result_type = if aggregation.preserves_type() {
+                inner_type
+            } else {
+                DataType::Number
+            };
+            let index = slots.len();
+            slots.push(AggregateSlot {
+                expr: inner,
+                aggregation,
+            });
+            slot_types.push(result_type);
+            Ok(Expr::Field {
+                name: slot_name(index),
+                index,
+            })
+        }
+        Expr::Field { name, .. } => Err(CalcError::new(format!(
+            "[{name}] must be aggregated, e.g. SUM([{name}])"
+        ))),
+        Expr::Literal(_) => Ok(expr),
+        Expr::Unary(op, operand) => Ok(Expr::Unary(op, Box::new(recurse(*operand)?))),
+        Expr::Binary(op, left, right) => {
+            let left = recurse(*left)?;
+            let right = recurse(*right)?;
+            Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+        }
+        Expr::Call(name, args) => {
+            let args = args.into_iter().map(&mut recurse).collect::<Result<Vec<_>, _>>()?;
+            Ok(Expr::Call(name, args))
+        }
+        Expr::If {
+            branches,
+            otherwise,
+        } => {
+            let branches = branches
+                .into_iter()
+                .map(|(condition, value)| Ok((recurse(condition)?, recurse(value)?)))
+                .collect::<Result<Vec<_>, CalcError>>()?;
+            let otherwise = otherwise.map(|otherwise| recurse(*otherwise)).transpose()?;
+            Ok(Expr::If {
+                branches,
+                otherwise: otherwise.map(Box::new),
+            })
+        }
+        Expr::Case {
+            subject,
+            branches,
+            otherwise,
+        } => {
+            let subject = recurse(*subject)?;
+            let branches = branches
+                .into_iter()
+                .map(|(candidate, value)| Ok((recurse(candidate)?, recurse(value)?)))
+                .collect::<Result<Vec<_>, CalcError>>()?;
+            let otherwise = otherwise.map(|otherwise| recurse(*otherwise)).transpose()?;
+            Ok(Expr::Case {
+                subject: Box::new(subject),
+                branches,
+                otherwise: otherwise.map(Box::new),
+            })
+        }
+    }
+}
+
+fn contains_aggregate(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(name, args) => {
+            name == "PERCENTILE"
+                || aggregation_for(name).is_some()
+                || args.iter().any(contains_aggregate)
+        }
+        Expr::Literal(_) | Expr::Field { .. } => false,
+        Expr::Unary(_, operand) => contains_aggregate(operand),
+        Expr::Binary(_, left, right) => contains_aggregate(left) || contains_aggregate(right),
+        Expr::If {
+            branches,
+            otherwise,
+        } => {
+            branches
+                .iter()
+                .any(|(condition, value)| contains_aggregate(condition) || contains_aggregate(value))
+                || otherwise.as_deref().is_some_and(contains_aggregate)
+        }
+        Expr::Case {
+            subject,
+            branches,
+            otherwise,
+        } => {
+            contains_aggregate(subject)
+                || branches
+                    .iter()
+                    .any(|(candidate, value)| contains_aggregate(candidate) || contains_aggregate(value))
+                || otherwise.as_deref().is_some_and(contains_aggregate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(formula: &str) -> Result<AggregateCalc, CalcError> {
+        let columns = [Column::new(
+            "Sales".to_string(),
+            DataType::Number,
+            vec![DataValue::Number(1.0)],
+        )];
+        let field = CalculatedField {
+            name: "Calc".to_string(),
+            formula: formula.to_string(),
+        };
+        AggregateCalc::compile(&field, &columns)
+    }
+
+    #[test]
+    fn percentiles_must_be_whole_numbers_from_1_to_99() {
+        for p in ["1", "90", "99"] {
+            let calc = compile(&format!("PERCENTILE([Sales], {p})")).expect("valid percentile");
+            assert_eq!(calc.slots[0].aggregation, Aggregation::Percentile(p.parse().unwrap()));
+        }
+        for p in ["0", "99.5", "100", "250", "[Sales]"] {
+            assert!(compile(&format!("PERCENTILE([Sales], {p})")).is_err(), "accepted {p}");
+        }
+    }
+}
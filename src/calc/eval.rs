@@ -0,0 +1,737 @@
+use std::cmp::Ordering;
+
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, Timelike};
+
+use crate::calc::parser::{BinaryOp, CalcError, Expr, UnaryOp};
+use crate::core::data::{DataType, DataValue};
+use crate::core::dates::DatePart;
+
+/// Type-checks `expr` against the available fields and binds each field
+/// reference to its index in `fields`. `DataType::Empty` stands for "unknown
+/// or null" and is accepted wherever a specific type is expected.
+pub fn check(expr: &mut Expr, fields: &[(&str, DataType)]) -> Result<DataType, CalcError> {
+    match expr {
+        Expr::Literal(value) => Ok(value.data_type()),
+        Expr::Field { name, index } => {
+            let position = fields
+                .iter()
+                .position(|(candidate, _)| candidate == name)
+                .ok_or_else(|| CalcError::new(format!("Unknown field [{name}]")))?;
+            *index = position;
+            Ok(fields[position].1.clone())
+        }
+        Expr::Unary(op, operand) => {
+            let operand_type = check(operand, fields)?;
+            let expected = match op {
+                UnaryOp::Negate => DataType::Number,
+                UnaryOp::Not => DataType::Boolean,
+            };
+            expect_type(&operand_type, &expected, || match op {
+                UnaryOp::Negate => "Negation".to_string(),
+                UnaryOp::Not => "NOT".to_string(),
+            })?;
+            Ok(expected)
+        }
+        Expr::Binary(op, left, right) => {
+            let left_type = check(left, fields)?;
+            let right_type = check(right, fields)?;
+            binary_type(*op, &left_type, &right_type)
+        }
+        Expr::Call(name, args) => {
+            let mut types = Vec::with_capacity(args.len());
+            for arg in args.iter_mut() {
+                types.push(check(arg, fields)?);
+            }
+            call_type(name, args, &types)
+        }
+        Expr::If {
+            branches,
+            otherwise,
+        } => {
+            let mut result = DataType::Empty;
+            for (condition, value) in branches.iter_mut() {
+                let condition_type = check(condition, fields)?;
+                expect_type(&condition_type, &DataType::Boolean, || "IF condition".to_string())?;
+                result = unify(&result, &check(value, fields)?, "IF")?;
+            }
+            if let Some(otherwise) = otherwise {
+                result = unify(&result, &check(otherwise, fields)?, "IF")?;
+            }
+            Ok(result)
+        }
+        Expr::Case {
+            subject,
+            branches,
+            otherwise,
+        } => {
+            let subject_type = check(subject, fields)?;
+            let mut result = DataType::Empty;
+            for (candidate, value) in branches.iter_mut() {
+                let candidate_type = check(candidate, fields)?;
+                unify(&subject_type, &candidate_type, "CASE WHEN")?;
+                result = unify(&result, &check(value, fields)?, "CASE")?;
+            }
+            if let Some(otherwise) = otherwise {
+                result = unify(&result, &check(otherwise, fields)?, "CASE")?;
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// Evaluates a checked expression for one row. `field` returns the value of
+/// the bound field index. Nulls, including cell errors, propagate as
+/// `DataValue::Empty`, as do runtime failures such as division by zero.
+pub fn eval(expr: &Expr, field: &dyn Fn(usize) -> DataValue) -> DataValue {
+    match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Field { index, .. } => match field(*index) {
+            DataValue::Error(_) => DataValue::Empty,
+            value => value,
+        },
+        Expr::Unary(UnaryOp::Negate, operand) => match eval(operand, field) {
+            DataValue::Number(number) => DataValue::Number(-number),
+            _ => DataValue::Empty,
+        },
+        Expr::Unary(UnaryOp::Not, operand) => match eval(operand, field) {
+            DataValue::Boolean(flag) => DataValue::Boolean(!flag),
+            _ => DataValue::Empty,
+        },
+        Expr::Binary(op, left, right) => {
+            binary_value(*op, eval(left, field), || eval(right, field))
+        }
+        Expr::Call(name, args) => {
+            let values = args.iter().map(|arg| eval(arg, field)).collect::<Vec<_>>();
+            call_value(name, &values)
+        }
+        Expr::If {
+            branches,
+            otherwise,
+        } => {
+            for (condition, value) in branches {
+                if eval(condition, field) == DataValue::Boolean(true) {
+                    return eval(value, field);
+                }
+            }
+            otherwise
+                .as_ref()
+                .map(|otherwise| eval(otherwise, field))
+                .unwrap_or(DataValue::Empty)
+        }
+        Expr::Case {
+            subject,
+            branches,
+            otherwise,
+        } => {
+            let subject = eval(subject, field);
+            for (candidate, value) in branches {
+                let candidate = eval(candidate, field);
+                if subject != DataValue::Empty && subject.compare(&candidate) == Ordering::Equal {
+                    return eval(value, field);
+                }
+            }
+            otherwise
+                .as_ref()
+                .map(|otherwise| eval(otherwise, field))
+                .unwrap_or(DataValue::Empty)
+        }
+    }
+}
+
+fn type_name(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::String => "String",
+        DataType::Number => "Number",
+        DataType::Date => "Date",
+        DataType::DateTime => "DateTime",
+        DataType::Time => "Time",
+        DataType::Duration => "Duration",
+        DataType::Boolean => "Boolean",
+        DataType::Empty => "Null",
+    }
+}
+
+fn expect_type(
+    actual: &DataType,
+    expected: &DataType,
+    context: impl FnOnce() -> String,
+) -> Result<(), CalcError> {
+    let date_like = *expected == DataType::Date && *actual == DataType::DateTime;
+    if *actual == DataType::Empty || *expected == DataType::Empty || actual == expected || date_like {
+        Ok(())
+    } else {
+        Err(CalcError::new(format!(
+            "{} expects {} but got {}",
+            context(),
+            type_name(expected),
+            type_name(actual)
+        )))
+    }
+}
+
+fn unify(left: &DataType, right: &DataType, context: &str) -> Result<DataType, CalcError> {
+    match (left, right) {
+        (DataType::Empty, other) | (other, DataType::Empty) => Ok(other.clone()),
+        (left, right) if left == right => Ok(left.clone()),
+        (left, right) => Err(CalcError::new(format!(
+            "{context} mixes {} and {} values",
+            type_name(left),
+            type_name(right)
+        ))),
+    }
+}
+
+fn binary_type(op: BinaryOp, left: &DataType, right: &DataType) -> Result<DataType, CalcError> {
+    use DataType::{Boolean, Date, DateTime, Empty, Number, String};
+    let mismatch = || {
+        CalcError::new(format!(
+            "Cannot apply '{}' to {} and {}",
+            op.symbol(),
+            type_name(left),
+            type_name(right)
+        ))
+    };
+    match op {
+        BinaryOp::Add => match (left, right) {
+            (Number | Empty, Number | Empty) => Ok(Number),
+            (String, String | Empty) | (Empty, String) => Ok(String),
+            (Date, Number | Empty) | (Number, Date) => Ok(Date),
+            (DateTime, Number | Empty) | (Number, DateTime) => Ok(DateTime),
+            _ => Err(mismatch()),
+        },
+        BinaryOp::Subtract => match (left, right) {
+            (Number | Empty, Number | Empty) => Ok(Number),
+            (Date, Number) => Ok(Date),
+            (DateTime, Number) => Ok(DateTime),
+            (Date | DateTime, Date | DateTime) | (Empty, Date | DateTime) => Ok(Number),
+            (Date | DateTime, Empty) => Ok(Empty),
+            _ => Err(mismatch()),
+        },
+        BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => match (left, right) {
+            (Number | Empty, Number | Empty) => Ok(Number),
+            _ => Err(mismatch()),
+        },
+        BinaryOp::Equal
+        | BinaryOp::NotEqual
+        | BinaryOp::Less
+        | BinaryOp::LessEqual
+        | BinaryOp::Greater
+        | BinaryOp::GreaterEqual => {
+            unify(left, right, "Comparison").map_err(|_| mismatch())?;
+            Ok(Boolean)
+        }
+        BinaryOp::And | BinaryOp::Or => match (left, right) {
+            (Boolean | Empty, Boolean | Empty) => Ok(Boolean),
+            _ => Err(mismatch()),
+        },
+    }
+}
+
+fn binary_value(op: BinaryOp, left: DataValue, right: impl FnOnce() -> DataValue) -> DataValue {
+    use DataValue::{Boolean, Date, DateTime, Empty, Number, String};
+    match op {
+        BinaryOp::And => match left {
+            Boolean(false) => Boolean(false),
+            left => match (left, right()) {
+                (_, Boolean(false)) => Boolean(false),
+                (Boolean(true), Boolean(true)) => Boolean(true),
+                _ => Empty,
+            },
+        },
+        BinaryOp::Or => match left {
+            Boolean(true) => Boolean(true),
+            left => match (left, right()) {
+                (_, Boolean(true)) => Boolean(true),
+                (Boolean(false), Boolean(false)) => Boolean(false),
+                _ => Empty,
+            },
+        },
+        _ => match (op, left, right()) {
+            (_, Empty, _) | (_, _, Empty) => Empty,
+            (BinaryOp::Add, Number(a), Number(b)) => Number(a + b),
+            (BinaryOp::Add, String(a), String(b)) => String(a + &b),
+            (BinaryOp::Add, Date(date), Number(days)) | (BinaryOp::Add, Number(days), Date(date)) => {
+                add_days(date, days)
+            }
+            (BinaryOp::Subtract, Number(a), Number(b)) => Number(a - b),
+            (BinaryOp::Subtract, Date(date), Number(days)) => add_days(date, -days),
+            (BinaryOp::Subtract, Date(a), Date(b)) => Number((a - b).num_days() as f64),
+            (BinaryOp::Add, DateTime(datetime), Number(days))
+            | (BinaryOp::Add, Number(days), DateTime(datetime)) => add_fractional_days(datetime, days),
+            (BinaryOp::Subtract, DateTime(datetime), Number(days)) => {
+                add_fractional_days(datetime, -days)
+            }
+            (BinaryOp::Subtract, a @ (Date(_) | DateTime(_)), b @ (Date(_) | DateTime(_))) => {
+                match (a.as_datetime(), b.as_datetime()) {
+                    (Some(a), Some(b)) => Number((a - b).num_seconds() as f64 / 86_400.0),
+                    _ => Empty,
+                }
+            }
+            (BinaryOp::Multiply, Number(a), Number(b)) => Number(a * b),
+            (BinaryOp::Divide, Number(_), Number(0.0)) => Empty,
+            (BinaryOp::Divide, Number(a), Number(b)) => Number(a / b),
+            (BinaryOp::Modulo, Number(_), Number(0.0)) => Empty,
+            (BinaryOp::Modulo, Number(a), Number(b)) => Number(a % b),
+            (op, left, right) => {
+                let ordering = left.compare(&right);
+                match op {
+                    BinaryOp::Equal => Boolean(ordering == Ordering::Equal),
+                    BinaryOp::NotEqual => Boolean(ordering != Ordering::Equal),
+                    BinaryOp::Less => Boolean(ordering == Ordering::Less),
+                    BinaryOp::LessEqual => Boolean(ordering != Ordering::Greater),
+                    BinaryOp::Greater => Boolean(ordering == Ordering::Greater),
+                    BinaryOp::GreaterEqual => Boolean(ordering != Ordering::Less),
+                    _ => Empty,
+                }
+            }
+        },
+    }
+}
+
+fn add_days(date: NaiveDate, days: f64) -> DataValue {
+    date.checked_add_signed(Duration::days(days.trunc() as i64))
+        .map(DataValue::Date)
+        .unwrap_or(DataValue::Empty)
+}
+
+/// Timestamps move by fractions of a day, e.g. `[Start] + 0.5` is twelve
+/// hours later.
+fn add_fractional_days(datetime: NaiveDateTime, days: f64) -> DataValue {
+    Duration::try_seconds((days * 86_400.0).round() as i64)
+        .and_then(|delta| datetime.checked_add_signed(delta))
+        .map(DataValue::DateTime)
+        .unwrap_or(DataValue::Empty)
+}
+
+/// Checks arity and argument types of a function call and returns its
+/// result type. `Empty` in `params` accepts any type.
+fn expect_args(
+    name: &str,
+    types: &[DataType],
+    params: &[DataType],
+    required: usize,
+) -> Result<(), CalcError> {
+    if types.len() < required || types.len() > params.len() {
+        let expected = if required == params.len() {
+            format!("{required}")
+        } else {
+            format!("{required} to {}", params.len())
+        };
+        return Err(CalcError::new(format!(
+            "{name} expects {expected} argument(s) but got {}",
+            types.len()
+        )));
+    }
+    for (position, (actual, expected)) in types.iter().zip(params).enumerate() {
+        expect_type(actual, expected, || format!("{name} argument {}", position + 1))?;
+    }
+    Ok(())
+}
+
+/// Validates a date part argument when it is written as a literal.
+fn expect_date_part(name: &str, arg: &Expr) -> Result<(), CalcError> {
+    match arg {
+        Expr::Literal(DataValue::String(part)) if DatePart::parse(part).is_none() => {
+            Err(CalcError::new(format!(
+                "{name} does not know the date part '{part}'; use one of year, quarter, month, week, iso-week, day, weekday, dayofyear, hour, minute"
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn call_type(name: &str, args: &[Expr], types: &[DataType]) -> Result<DataType, CalcError> {
+    use DataType::{Boolean, Date, DateTime, Empty, Number, String, Time};
+    // Date functions given a timestamp keep returning timestamps.
+    let date_result = |index: usize| match types.get(index) {
+        Some(DateTime) => DateTime,
+        _ => Date,
+    };
+    match name {
+        "LEN" | "FIND" => {
+            let params: &[DataType] = if name == "LEN" { &[String] } else { &[String, String] };
+            expect_args(name, types, params, params.len())?;
+            Ok(Number)
+        }
+        "UPPER" | "LOWER" | "TRIM" => {
+            expect_args(name, types, &[String], 1)?;
+            Ok(String)
+        }
+        "LEFT" | "RIGHT" => {
+            expect_args(name, types, &[String, Number], 2)?;
+            Ok(String)
+        }
+        "MID" => {
+            expect_args(name, types, &[String, Number, Number], 2)?;
+            Ok(String)
+        }
+        "CONTAINS" | "STARTSWITH" | "ENDSWITH" => {
+            expect_args(name, types, &[String, String], 2)?;
+            Ok(Boolean)
+        }
+        "REPLACE" => {
+            expect_args(name, types, &[String, String, String], 3)?;
+            Ok(String)
+        }
+        "STR" => {
+            expect_args(name, types, &[Empty], 1)?;
+            Ok(String)
+        }
+        "ABS" | "SQRT" | "FLOOR" | "CEILING" | "EXP" | "LN" => {
+            expect_args(name, types, &[Number], 1)?;
+            Ok(Number)
+        }
+        "ROUND" | "LOG" => {
+            expect_args(name, types, &[Number, Number], 1)?;
+            Ok(Number)
+        }
+        "POWER" => {
+            expect_args(name, types, &[Number, Number], 2)?;
+            Ok(Number)
+        }
+        "INT" | "FLOAT" => {
+            expect_args(name, types, &[Empty], 1)?;
+            Ok(Number)
+        }
+        "YEAR" | "QUARTER" | "MONTH" | "WEEK" | "DAY" | "WEEKDAY" => {
+            expect_args(name, types, &[Date], 1)?;
+            Ok(Number)
+        }
+        "HOUR" | "MINUTE" | "SECOND" => {
+            expect_args(name, types, &[Empty], 1)?;
+            match &types[0] {
+                DateTime | Time | Empty => Ok(Number),
+                other => Err(CalcError::new(format!(
+                    "{name} argument 1 expects DateTime or Time but got {}",
+                    type_name(other)
+                ))),
+            }
+        }
+        "DATEPART" => {
+            expect_args(name, types, &[String, Date], 2)?;
+            expect_date_part(name, &args[0])?;
+            Ok(Number)
+        }
+        "DATETRUNC" => {
+            expect_args(name, types, &[String, Date], 2)?;
+            expect_date_part(name, &args[0])?;
+            Ok(date_result(1))
+        }
+        "DATEDIFF" => {
+            expect_args(name, types, &[String, Date, Date], 3)?;
+            expect_date_part(name, &args[0])?;
+            Ok(Number)
+        }
+        "DATEADD" => {
+            expect_args(name, types, &[String, Number, Date], 3)?;
+            expect_date_part(name, &args[0])?;
+            Ok(date_result(2))
+        }
+        "MAKEDATE" => {
+            expect_args(name, types, &[Number, Number, Number], 3)?;
+            Ok(Date)
+        }
+        "DATE" => {
+            expect_args(name, types, &[Empty], 1)?;
+            Ok(Date)
+        }
+        "TODAY" => {
+            expect_args(name, types, &[], 0)?;
+            Ok(Date)
+        }
+        "ISNULL" => {
+            expect_args(name, types, &[Empty], 1)?;
+            Ok(Boolean)
+        }
+        "IFNULL" => {
+            expect_args(name, types, &[Empty, Empty], 2)?;
+            unify(&types[0], &types[1], name)
+        }
+        "ZN" => {
+            expect_args(name, types, &[Number], 1)?;
+            Ok(Number)
+        }
+        "IIF" => {
+            expect_args(name, types, &[Boolean, Empty, Empty], 3)?;
+            unify(&types[1], &types[2], name)
+        }
+        _ => Err(CalcError::new(format!("Unknown function {name}"))),
+    }
+}
+
+fn call_value(name: &str, args: &[DataValue]) -> DataValue {
+    use DataValue::{Boolean, Date, DateTime, Empty, Number, String, Time};
+    let number = |index: usize| args.get(index).and_then(DataValue::as_f64);
+    let text = |index: usize| match args.get(index) {
+        Some(String(value)) => Some(value.as_str()),
+        _ => None,
+    };
+    let date = |index: usize| args.get(index).and_then(DataValue::as_date);
+    let datetime = |index: usize| args.get(index).and_then(DataValue::as_datetime);
+    let part = |index: usize| text(index).and_then(DatePart::parse);
+    let numeric = |value: Option<f64>| value.filter(|value| value.is_finite()).map(Number).unwrap_or(Empty);
+
+    match name {
+        "ISNULL" => Boolean(args[0] == Empty),
+        "IFNULL" => match &args[0] {
+            Empty => args[1].clone(),
+            value => value.clone(),
+        },
+        "ZN" => Number(number(0).unwrap_or(0.0)),
+        "IIF" => match args[0] {
+            Boolean(true) => args[1].clone(),
+            Boolean(false) => args[2].clone(),
+            _ => Empty,
+        },
+        "STR" => match &args[0] {
+            Empty => Empty,
+            value => String(value.display_string()),
+        },
+        "INT" | "FLOAT" => {
+            let value = match &args[0] {
+                Number(value) => Some(*value),
+                String(value) => value.trim().parse::<f64>().ok(),
+                Boolean(flag) => Some(if *flag { 1.0 } else { 0.0 }),
+                _ => None,
+            };
+            numeric(if name == "INT" { value.map(f64::trunc) } else { value })
+        }
+        "DATE" => match &args[0] {
+            Date(value) => Date(*value),
+            DateTime(value) => Date(value.date()),
+            String(value) => NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                .map(Date)
+                .unwrap_or(Empty),
+            _ => Empty,
+        },
+        "TODAY" => Date(Local::now().date_naive()),
+        "LEN" => text(0).map(|value| Number(value.chars().count() as f64)).unwrap_or(Empty),
+        "FIND" => match (text(0), text(1)) {
+            (Some(haystack), Some(needle)) => Number(
+                haystack
+                    .find(needle)
+                    .map(|byte| haystack[..byte].chars().count() as f64 + 1.0)
+                    .unwrap_or(0.0),
+            ),
+            _ => Empty,
+        },
+        "UPPER" => text(0).map(|value| String(value.to_uppercase())).unwrap_or(Empty),
+        "LOWER" => text(0).map(|value| String(value.to_lowercase())).unwrap_or(Empty),
+        "TRIM" => text(0).map(|value| String(value.trim().to_string())).unwrap_or(Empty),
+        "LEFT" | "RIGHT" => match (text(0), number(1)) {
+            (Some(value), Some(count)) => {
+                let count = count.max(0.0) as usize;
+                let chars = value.chars().collect::<Vec<_>>();
+                let taken = if name == "LEFT" {
+                    chars.iter().take(count).collect()
+                } else {
+                    chars[chars.len().saturating_sub(count)..].iter().collect()
+                };
+                String(taken)
+            }
+            _ => Empty,
+        },
+        "MID" => match (text(0), number(1)) {
+            (Some(value), Some(start)) => {
+                let skip = (start.max(1.0) as usize) - 1;
+                let taken = value.chars().skip(skip);
+                String(match number(2) {
+                    Some(length) => taken.take(length.max(0.0) as usize).collect(),
+                    None => taken.collect(),
+                })
+            }
+            _ => Empty,
+        },
+        "CONTAINS" | "STARTSWITH" | "ENDSWITH" => match (text(0), text(1)) {
+            (Some(value), Some(needle)) => Boolean(match name {
+                "CONTAINS" => value.contains(needle),
+                "STARTSWITH" => value.starts_with(needle),
+                _ => value.ends_with(needle),
+            }),
+            _ => Empty,
+        },
+        "REPLACE" => match (text(0), text(1), text(2)) {
+            (Some(value), Some(from), Some(to)) if !from.is_empty() => String(value.replace(from, to)),
+            (Some(value), Some(_), Some(_)) => String(value.to_string()),
+            _ => Empty,
+        },
+        "ABS" => numeric(number(0).map(f64::abs)),
+        "SQRT" => numeric(number(0).filter(|value| *value >= 0.0).map(f64::sqrt)),
+        "FLOOR" => numeric(number(0).map(f64::floor)),
+        "CEILING" => numeric(number(0).map(f64::ceil)),
+        "EXP" => numeric(number(0).map(f64::exp)),
+        "LN" => numeric(number(0).filter(|value| *value > 0.0).map(f64::ln)),
+        "LOG" => {
+            let base = if args.len() > 1 { number(1) } else { Some(10.0) };
+            numeric(
+                number(0)
+                    .zip(base)
+                    .filter(|(value, base)| *value > 0.0 && *base > 0.0)
+                    .map(|(value, base)| value.log(base)),
+            )
+        }
+        "ROUND" => {
+            let digits = if args.len() > 1 { number(1) } else { Some(0.0) };
+            numeric(number(0).zip(digits).map(|(value, digits)| {
+                let factor = 10f64.powi(digits as i32);
+                (value * factor).round() / factor
+            }))
+        }
+        "POWER" => numeric(number(0).zip(number(1)).map(|(base, exponent)| base.powf(exponent))),
+        "YEAR" | "QUARTER" | "MONTH" | "WEEK" | "DAY" | "WEEKDAY" => {
+            let part = DatePart::parse(name).unwrap_or(DatePart::Day);
+            date(0).map(|value| Number(part.extract(value) as f64)).unwrap_or(Empty)
+        }
+        "HOUR" | "MINUTE" | "SECOND" => {
+            let time = match &args[0] {
+                DateTime(value) => value.time(),
+                Time(value) => *value,
+                _ => return Empty,
+            };
+            Number(f64::from(match name {
+                "HOUR" => time.hour(),
+                "MINUTE" => time.minute(),
+                _ => time.second(),
+            }))
+        }
+        "DATEPART" => match (part(0), datetime(1)) {
+            (Some(part), Some(value)) => Number(part.extract_datetime(value) as f64),
+            _ => Empty,
+        },
+        "DATETRUNC" => match (part(0), &args[1]) {
+            (Some(part), DateTime(value)) => DateTime(part.truncate_datetime(*value)),
+            (Some(part), Date(value)) => Date(part.truncate(*value)),
+            _ => Empty,
+        },
+        "DATEDIFF" => match (part(0), datetime(1), datetime(2)) {
+            (Some(part), Some(start), Some(end)) => {
+                Number(part.diff_datetime(start, end) as f64)
+            }
+            _ => Empty,
+        },
+        "DATEADD" => match (part(0), number(1), &args[2]) {
+            (Some(part), Some(amount), DateTime(value)) => part
+                .add_datetime(*value, amount.trunc() as i64)
+                .map(DateTime)
+                .unwrap_or(Empty),
+            (Some(part), Some(amount), Date(value)) => part
+                .add(*value, amount.trunc() as i64)
+                .map(Date)
+                .unwrap_or(Empty),
+            _ => Empty,
+        },
+        "MAKEDATE" => match (number(0), number(1), number(2)) {
+            (Some(year), Some(month), Some(day)) => {
+                NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
+                    .map(Date)
+                    .unwrap_or(Empty)
+            }
+            _ => Empty,
+        },
+        _ => Empty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc::aggregate::AggregateCalc;
+    use crate::calc::field::CalculatedField;
+    use crate::calc::parser::parse;
+    use crate::core::column::Column;
+
+    /// Checks and evaluates `formula` for one row holding `fields`.
+    fn evaluate(formula: &str, fields: &[(&str, DataValue)]) -> Result<DataValue, CalcError> {
+        let mut expr = parse(formula)?;
+        let schema = fields
+            .iter()
+            .map(|(name, value)| (*name, value.data_type()))
+            .collect::<Vec<_>>();
+        check(&mut expr, &schema)?;
+        Ok(eval(&expr, &|index| fields[index].1.clone()))
+    }
+
+    fn number(value: f64) -> Result<DataValue, CalcError> {
+        Ok(DataValue::Number(value))
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence() {
+        assert_eq!(evaluate("2 + 3 * 4", &[]), number(14.0));
+        assert_eq!(evaluate("(2 + 3) * 4", &[]), number(20.0));
+        assert_eq!(evaluate("10 - 2 - 3", &[]), number(5.0));
+        assert_eq!(evaluate("7 % 4 * 2", &[]), number(6.0));
+    }
+
+    #[test]
+    fn unary_minus_negates_its_operand() {
+        let x = [("x", DataValue::Number(3.0))];
+        assert_eq!(evaluate("-[x] + 1", &x), number(-2.0));
+        assert_eq!(evaluate("-2 * -[x]", &x), number(6.0));
+        assert_eq!(evaluate("- -[x]", &x), number(3.0));
+        assert!(evaluate("-'text'", &[]).is_err());
+    }
+
+    #[test]
+    fn strings_and_numbers_only_mix_through_conversions() {
+        assert_eq!(
+            evaluate("'a' + 1", &[]),
+            Err(CalcError::new("Cannot apply '+' to String and Number"))
+        );
+        assert_eq!(evaluate("STR(1.5) + 'x'", &[]), Ok(DataValue::String("1.5x".to_string())));
+        assert_eq!(evaluate("FLOAT(' 2.5 ') * 2", &[]), number(5.0));
+        assert_eq!(evaluate("INT('7.9')", &[]), number(7.0));
+        assert_eq!(evaluate("FLOAT('abc')", &[]), Ok(DataValue::Empty));
+    }
+
+    #[test]
+    fn division_by_zero_is_null() {
+        let x = [("x", DataValue::Number(0.0))];
+        assert_eq!(evaluate("1 / [x]", &x), Ok(DataValue::Empty));
+        assert_eq!(evaluate("1 % [x]", &x), Ok(DataValue::Empty));
+        assert_eq!(evaluate("ZN(1 / [x]) + 1", &x), number(1.0));
+    }
+
+    #[test]
+    fn nulls_and_cell_errors_propagate() {
+        let fields = [("x", DataValue::Error("#N/A".to_string())), ("y", DataValue::Number(1.0))];
+        assert_eq!(evaluate("[x] + [y]", &fields), Ok(DataValue::Empty));
+        assert_eq!(evaluate("ISNULL([x])", &fields), Ok(DataValue::Boolean(true)));
+    }
+
+    #[test]
+    fn unknown_fields_and_functions_are_errors() {
+        let x = [("x", DataValue::Number(1.0))];
+        assert_eq!(evaluate("[Nope] + 1", &x), Err(CalcError::new("Unknown field [Nope]")));
+        assert_eq!(evaluate("frobnicate([x])", &x), Err(CalcError::new("Unknown function FROBNICATE")));
+    }
+
+    #[test]
+    fn aggregates_belong_in_aggregate_calculations() {
+        let columns = [Column::new(
+            "Sales".to_string(),
+            DataType::Number,
+            vec![DataValue::Number(1.0)],
+        )];
+        let field = |formula: &str| CalculatedField {
+            name: "Calc".to_string(),
+            formula: formula.to_string(),
+        };
+
+        let row_level = |formula: &str| field(formula).compile(&columns).map(|(_, data_type)| data_type);
+        assert_eq!(row_level("[Sales] * 2"), Ok(DataType::Number));
+        assert_eq!(row_level("SUM([Sales])"), Err(CalcError::new("Unknown function SUM")));
+
+        let aggregate = AggregateCalc::compile(&field("SUM([Sales]) / COUNT([Sales])"), &columns)
+            .expect("valid aggregate calculation");
+        assert_eq!(aggregate.slots.len(), 2);
+        assert_eq!(
+            aggregate.evaluate(&[DataValue::Number(10.0), DataValue::Number(4.0)]),
+            DataValue::Number(2.5)
+        );
+        assert_eq!(
+            AggregateCalc::compile(&field("[Sales] * 2"), &columns).map(|calc| calc.data_type),
+            Err(CalcError::new("[Sales] must be aggregated, e.g. SUM([Sales])"))
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+use crate::calc::eval::{check, eval};
+use crate::calc::parser::{parse, CalcError, Expr};
+use crate::core::column::Column;
+use crate::core::data::{DataType, DataValue};
+use crate::core::workbook::Worksheet;
+use crate::worker::Progress;
+
+/// A named, row-level formula that becomes a virtual column of the sheet.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CalculatedField {
+    pub name: String,
+    pub formula: String,
+}
+
+impl CalculatedField {
+    /// Parses and type-checks the formula against `columns`, returning the
+    /// bound expression and its result type.
+    pub fn compile(&self, columns: &[Column]) -> Result<(Expr, DataType), CalcError> {
+        if self.name.trim().is_empty() {
+            return Err(CalcError::new("Calculated field needs a name"));
+        }
+        if columns.iter().any(|column| column.name == self.name) {
+            return Err(CalcError::new(format!(
+                "A field named [{}] already exists",
+                self.name
+            )));
+        }
+        let mut expr = parse(&self.formula)?;
+        let schema = columns
+            .iter()
+            .map(|column| (column.name.as_str(), column.data_type.clone()))
+            .collect::<Vec<_>>();
+        let data_type = check(&mut expr, &schema)?;
+        Ok((expr, data_type))
+    }
+
+    /// Evaluates the field for every row; the column is `None` once
+    /// `progress` is cancelled.
+    pub fn evaluate(
+        &self,
+        columns: &[Column],
+        row_count: usize,
+        progress: &Progress,
+    ) -> Result<Option<Column>, CalcError> {
+        let (expr, data_type) = self.compile(columns)?;
+        let mut values = Vec::with_capacity(row_count);
+        for row in 0..row_count {
+            if !progress.report_rows(row, row_count) {
+                return Ok(None);
+            }
+            values.push(eval(&expr, &|index| columns[index].value(row)));
+        }
+        let data_type = match data_type {
+            DataType::Empty => values
+                .iter()
+                .map(DataValue::data_type)
+                .find(|data_type| *data_type != DataType::Empty)
+                .unwrap_or(DataType::Empty),
+            known => known,
+        };
+        Ok(Some(Column::new(self.name.clone(), data_type, values)))
+    }
+}
+
+/// Returns a copy of `sheet` with every valid calculated field appended as
+/// a column, in definition order so later fields can use earlier ones. The
+/// second element holds the error, if any, for each field. Returns `None`
+/// once `progress` is cancelled.
+pub fn with_calculated_fields(
+    sheet: &Worksheet,
+    fields: &[CalculatedField],
+    progress: &Progress,
+) -> Option<(Worksheet, Vec<Option<CalcError>>)> {
+    let mut worksheet = sheet.clone();
+    let field_count = fields.len().max(1) as f32;
+    let mut errors = Vec::with_capacity(fields.len());
+    for (index, field) in fields.iter().enumerate() {
+        let part = progress.part(index as f32 / field_count, (index + 1) as f32 / field_count);
+        match field.evaluate(&worksheet.columns, worksheet.row_count, &part) {
+            Ok(Some(column)) => {
+                worksheet.columns.push(column);
+                errors.push(None);
+            }
+            Ok(None) => return None,
+            Err(error) => errors.push(Some(error)),
+        }
+    }
+    Some((worksheet, errors))
+}
@@ -0,0 +1,4 @@
+pub mod aggregate;
+pub mod eval;
+pub mod field;
+pub mod parser;
@@ -0,0 +1,601 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use crate::core::data::DataValue;
+
+/// A problem found while parsing, checking or binding a formula.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalcError {
+    pub message: String,
+    /// Character offset into the formula, when the problem has a location.
+    pub position: Option<usize>,
+}
+
+impl CalcError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            position: None,
+        }
+    }
+
+    pub fn at(message: impl Into<String>, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            position: Some(position),
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{} (at character {})", self.message, position + 1),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Equal => "=",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(DataValue),
+    /// A column reference; `index` is filled in when the formula is bound
+    /// to a worksheet.
+    Field {
+        name: String,
+        index: usize,
+    },
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// Function call with an upper-cased name.
+    Call(String, Vec<Expr>),
+    If {
+        branches: Vec<(Expr, Expr)>,
+        otherwise: Option<Box<Expr>>,
+    },
+    Case {
+        subject: Box<Expr>,
+        branches: Vec<(Expr, Expr)>,
+        otherwise: Option<Box<Expr>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Date(NaiveDate),
+    Field(String),
+    Ident(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 16] = [
+    "<=", ">=", "<>", "!=", "==", "+", "-", "*", "/", "%", "(", ")", ",", "=", "<", ">",
+];
+
+const KEYWORDS: [&str; 13] = [
+    "IF", "THEN", "ELSEIF", "ELSE", "END", "CASE", "WHEN", "AND", "OR", "NOT", "TRUE", "FALSE",
+    "NULL",
+];
+
+/// Parses a formula such as `IF [Sales] > 1000 THEN "High" ELSE "Low" END`.
+///
+/// Fields are written in brackets (`[Order Date]`) or, when the name is a
+/// plain identifier, bare (`Sales`). Strings use single or double quotes and
+/// date literals are written `#2024-01-31#`.
+pub fn parse(formula: &str) -> Result<Expr, CalcError> {
+    let tokens = tokenize(formula)?;
+    let mut parser = Parser {
+        tokens,
+        cursor: 0,
+        end: formula.chars().count(),
+    };
+    if parser.tokens.is_empty() {
+        return Err(CalcError::new("Formula is empty"));
+    }
+    let expr = parser.expression()?;
+    match parser.tokens.get(parser.cursor) {
+        None => Ok(expr),
+        Some((token, position)) => Err(CalcError::at(
+            format!("Unexpected {} after the end of the expression", describe(token)),
+            *position,
+        )),
+    }
+}
+
+fn tokenize(formula: &str) -> Result<Vec<(Token, usize)>, CalcError> {
+    let chars = formula.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let ch = chars[index];
+        let start = index;
+        if ch.is_whitespace() {
+            index += 1;
+        } else if ch.is_ascii_digit() || (ch == '.' && chars.get(index + 1).is_some_and(char::is_ascii_digit)) {
+            while index < chars.len() && (chars[index].is_ascii_digit() || chars[index] == '.') {
+                index += 1;
+            }
+            let text = chars[start..index].iter().collect::<String>();
+            let number = text
+                .parse::<f64>()
+                .map_err(|_| CalcError::at(format!("Invalid number '{text}'"), start))?;
+            tokens.push((Token::Number(number), start));
+        } else if ch == '"' || ch == '\'' {
+            let (text, next) = read_delimited(&chars, index + 1, ch)
+                .ok_or_else(|| CalcError::at("Unterminated string", start))?;
+            tokens.push((Token::Text(text), start));
+            index = next;
+        } else if ch == '[' {
+            let (name, next) = read_delimited(&chars, index + 1, ']')
+                .ok_or_else(|| CalcError::at("Missing ']' after field name", start))?;
+            tokens.push((Token::Field(name), start));
+            index = next;
+        } else if ch == '#' {
+            let (text, next) = read_delimited(&chars, index + 1, '#')
+                .ok_or_else(|| CalcError::at("Missing '#' after date literal", start))?;
+            let date = NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(|_| {
+                CalcError::at(format!("Invalid date '{text}', expected #YYYY-MM-DD#"), start)
+            })?;
+            tokens.push((Token::Date(date), start));
+            index = next;
+        } else if ch.is_alphabetic() || ch == '_' {
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_') {
+                index += 1;
+            }
+            tokens.push((Token::Ident(chars[start..index].iter().collect()), start));
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| {
+            symbol
+                .chars()
+                .enumerate()
+                .all(|(offset, expected)| chars.get(index + offset) == Some(&expected))
+        }) {
+            tokens.push((Token::Symbol(symbol), start));
+            index += symbol.chars().count();
+        } else {
+            return Err(CalcError::at(format!("Unexpected character '{ch}'"), start));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads up to the closing `delimiter`, treating a doubled delimiter as an
+/// escaped literal. Returns the text and the index after the delimiter.
+fn read_delimited(chars: &[char], mut index: usize, delimiter: char) -> Option<(String, usize)> {
+    let mut text = String::new();
+    while index < chars.len() {
+        if chars[index] == delimiter {
+            if chars.get(index + 1) == Some(&delimiter) {
+                text.push(delimiter);
+                index += 2;
+                continue;
+            }
+            return Some((text, index + 1));
+        }
+        text.push(chars[index]);
+        index += 1;
+    }
+    None
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(number) => format!("number {number}"),
+        Token::Text(text) => format!("string \"{text}\""),
+        Token::Date(date) => format!("date #{date}#"),
+        Token::Field(name) => format!("field [{name}]"),
+        Token::Ident(name) => format!("'{name}'"),
+        Token::Symbol(symbol) => format!("'{symbol}'"),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    cursor: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.cursor)
+            .map(|(_, position)| *position)
+            .unwrap_or(self.end)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.cursor).map(|(token, _)| token.clone());
+        self.cursor += 1;
+        token
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(name)) if name.eq_ignore_ascii_case(keyword))
+    }
+
+    fn at_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(candidate)) if *candidate == symbol)
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), CalcError> {
+        if self.at_keyword(keyword) {
+            self.cursor += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{keyword}'")))
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), CalcError> {
+        if self.at_symbol(symbol) {
+            self.cursor += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{symbol}'")))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> CalcError {
+        match self.peek() {
+            Some(token) => CalcError::at(
+                format!("Expected {expected} but found {}", describe(token)),
+                self.position(),
+            ),
+            None => CalcError::at(
+                format!("Expected {expected} but the formula ended"),
+                self.position(),
+            ),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, CalcError> {
+        self.or()
+    }
+
+    fn or(&mut self) -> Result<Expr, CalcError> {
+        let mut left = self.and()?;
+        while self.at_keyword("OR") {
+            self.cursor += 1;
+            let right = self.and()?;
+            left = Expr::Binary(BinaryOp::Or, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, CalcError> {
+        let mut left = self.not()?;
+        while self.at_keyword("AND") {
+            self.cursor += 1;
+            let right = self.not()?;
+            left = Expr::Binary(BinaryOp::And, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, CalcError> {
+        if self.at_keyword("NOT") {
+            self.cursor += 1;
+            let operand = self.not()?;
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(operand)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, CalcError> {
+        let left = self.additive()?;
+        let op = match self.peek() {
+            Some(Token::Symbol("=" | "==")) => BinaryOp::Equal,
+            Some(Token::Symbol("!=" | "<>")) => BinaryOp::NotEqual,
+            Some(Token::Symbol("<")) => BinaryOp::Less,
+            Some(Token::Symbol("<=")) => BinaryOp::LessEqual,
+            Some(Token::Symbol(">")) => BinaryOp::Greater,
+            Some(Token::Symbol(">=")) => BinaryOp::GreaterEqual,
+            _ => return Ok(left),
+        };
+        self.cursor += 1;
+        let right = self.additive()?;
+        Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn additive(&mut self) -> Result<Expr, CalcError> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("+")) => BinaryOp::Add,
+                Some(Token::Symbol("-")) => BinaryOp::Subtract,
+                _ => return Ok(left),
+            };
+            self.cursor += 1;
+            let right = self.multiplicative()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, CalcError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("*")) => BinaryOp::Multiply,
+                Some(Token::Symbol("/")) => BinaryOp::Divide,
+                Some(Token::Symbol("%")) => BinaryOp::Modulo,
+                _ => return Ok(left),
+            };
+            self.cursor += 1;
+            let right = self.unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CalcError> {
+        if self.at_symbol("-") {
+            self.cursor += 1;
+            let operand = self.unary()?;
+            return Ok(Expr::Unary(UnaryOp::Negate, Box::new(operand)));
+        }
+        if self.at_symbol("+") {
+            self.cursor += 1;
+            return self.unary();
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, CalcError> {
+        let position = self.position();
+        match self.advance() {
+            Some(Token::Number(number)) => Ok(Expr::Literal(DataValue::Number(number))),
+            Some(Token::Text(text)) => Ok(Expr::Literal(DataValue::String(text))),
+            Some(Token::Date(date)) => Ok(Expr::Literal(DataValue::Date(date))),
+            Some(Token::Field(name)) => Ok(Expr::Field { name, index: 0 }),
+            Some(Token::Symbol("(")) => {
+                let inner = self.expression()?;
+                self.expect_symbol(")")?;
+                Ok(inner)
+            }
+            Some(Token::Ident(name)) => self.identifier(name, position),
+            Some(token) => Err(CalcError::at(
+                format!("Expected a value but found {}", describe(&token)),
+                position,
+            )),
+            None => Err(CalcError::at("Expected a value but the formula ended", position)),
+        }
+    }
+
+    fn identifier(&mut self, name: String, position: usize) -> Result<Expr, CalcError> {
+        let upper = name.to_ascii_uppercase();
+        match upper.as_str() {
+            "TRUE" => return Ok(Expr::Literal(DataValue::Boolean(true))),
+            "FALSE" => return Ok(Expr::Literal(DataValue::Boolean(false))),
+            "NULL" => return Ok(Expr::Literal(DataValue::Empty)),
+            "IF" => return self.if_expression(),
+            "CASE" => return self.case_expression(),
+            _ => {}
+        }
+        if self.at_symbol("(") {
+            self.cursor += 1;
+            let mut args = Vec::new();
+            if !self.at_symbol(")") {
+                loop {
+                    args.push(self.expression()?);
+                    if self.at_symbol(",") {
+                        self.cursor += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            self.expect_symbol(")")?;
+            return Ok(Expr::Call(upper, args));
+        }
+        if KEYWORDS.contains(&upper.as_str()) {
+            return Err(CalcError::at(format!("Unexpected keyword '{name}'"), position));
+        }
+        Ok(Expr::Field { name, index: 0 })
+    }
+
+    fn if_expression(&mut self) -> Result<Expr, CalcError> {
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.expression()?;
+            self.expect_keyword("THEN")?;
+            let result = self.expression()?;
+            branches.push((condition, result));
+            if self.at_keyword("ELSEIF") {
+                self.cursor += 1;
+            } else {
+                break;
+            }
+        }
+        let otherwise = self.else_branch()?;
+        self.expect_keyword("END")?;
+        Ok(Expr::If { branches, otherwise })
+    }
+
+    fn case_expression(&mut self) -> Result<Expr, CalcError> {
+        let subject = self.expression()?;
+        let mut branches = Vec::new();
+        while self.at_keyword("WHEN") {
+            self.cursor += 1;
+            let candidate = self.expression()?;
+            self.expect_keyword("THEN")?;
+            let result = self.expression()?;
+            branches.push((candidate, result));
+        }
+        if branches.is_empty() {
+            return Err(self.unexpected("'WHEN'"));
+        }
+        let otherwise = self.else_branch()?;
+        self.expect_keyword("END")?;
+        Ok(Expr::Case {
+            subject: Box::new(subject),
+            branches,
+            otherwise,
+        })
+    }
+
+    fn else_branch(&mut self) -> Result<Option<Box<Expr>>, CalcError> {
+        if self.at_keyword("ELSE") {
+            self.cursor += 1;
+            Ok(Some(Box::new(self.expression()?)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: f64) -> Box<Expr> {
+        Box::new(Expr::Literal(DataValue::Number(value)))
+    }
+
+    fn field(name: &str) -> Box<Expr> {
+        Box::new(Expr::Field {
+            name: name.to_string(),
+            index: 0,
+        })
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(
+            parse("1 + 2 * 3"),
+            Ok(Expr::Binary(
+                BinaryOp::Add,
+                number(1.0),
+                Box::new(Expr::Binary(BinaryOp::Multiply, number(2.0), number(3.0))),
+            ))
+        );
+        assert_eq!(
+            parse("(1 + 2) * 3"),
+            Ok(Expr::Binary(
+                BinaryOp::Multiply,
+                Box::new(Expr::Binary(BinaryOp::Add, number(1.0), number(2.0))),
+                number(3.0),
+            ))
+        );
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        assert_eq!(
+            parse("10 - 2 - 3"),
+            Ok(Expr::Binary(
+                BinaryOp::Subtract,
+                Box::new(Expr::Binary(BinaryOp::Subtract, number(10.0), number(2.0))),
+                number(3.0),
+            ))
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or_and_comparisons_tighter_than_both() {
+        assert_eq!(
+            parse("[a] > 1 OR [b] AND NOT [c]"),
+            Ok(Expr::Binary(
+                BinaryOp::Or,
+                Box::new(Expr::Binary(BinaryOp::Greater, field("a"), number(1.0))),
+                Box::new(Expr::Binary(
+                    BinaryOp::And,
+                    field("b"),
+                    Box::new(Expr::Unary(UnaryOp::Not, field("c"))),
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn unary_minus_applies_to_the_nearest_operand() {
+        assert_eq!(
+            parse("-2 * 3"),
+            Ok(Expr::Binary(
+                BinaryOp::Multiply,
+                Box::new(Expr::Unary(UnaryOp::Negate, number(2.0))),
+                number(3.0),
+            ))
+        );
+        assert_eq!(
+            parse("1 - -[x]"),
+            Ok(Expr::Binary(
+                BinaryOp::Subtract,
+                number(1.0),
+                Box::new(Expr::Unary(UnaryOp::Negate, field("x"))),
+            ))
+        );
+    }
+
+    #[test]
+    fn fields_are_bracketed_or_bare_and_functions_upper_cased() {
+        assert_eq!(
+            parse("round([Order Total], Digits)"),
+            Ok(Expr::Call("ROUND".to_string(), vec![*field("Order Total"), *field("Digits")]))
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_character() {
+        assert_eq!(parse("   "), Err(CalcError::new("Formula is empty")));
+        assert_eq!(parse("1 + 'abc"), Err(CalcError::at("Unterminated string", 4)));
+        assert_eq!(
+            parse("1 2"),
+            Err(CalcError::at("Unexpected number 2 after the end of the expression", 2))
+        );
+        assert_eq!(
+            parse("(1 + 2"),
+            Err(CalcError::at("Expected ')' but the formula ended", 6))
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use encoding_rs::{Encoding, WINDOWS_1252};
+use serde::{Deserialize, Serialize};
+
+use crate::connectors::schema::{parse_text, typed_column, unique_names};
+use crate::core::data::DataValue;
+use crate::core::workbook::{SheetDiagnostics, SheetInfo, SheetSource, Workbook, Worksheet};
+
+const DELIMITER_CANDIDATES: [char; 4] = [',', '\t', ';', '|'];
+const SNIFF_LINES: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CsvOptions {
+    /// Field separator; sniffed from the first lines of the file when `None`.
+    pub delimiter: Option<char>,
+    pub quote: char,
+    /// Source encoding; taken from the byte order mark when `None`, falling
+    /// back to UTF-8 and then Windows-1252.
+    #[serde(with = "encoding_name")]
+    pub encoding: Option<&'static Encoding>,
+    /// Whether the first record holds column names; guessed when `None`.
+    pub has_header: Option<bool>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: None,
+            quote: '"',
+            encoding: None,
+            has_header: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn for_path(path: &Path) -> Self {
+        let is_tab_separated = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("tsv") || extension.eq_ignore_ascii_case("tab")
+            });
+        Self {
+            delimiter: is_tab_separated.then_some('\t'),
+            ..Self::default()
+        }
+    }
+}
+
+/// Stores an encoding by its name, e.g. `UTF-8`.
+mod encoding_name {
+    use encoding_rs::Encoding;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        encoding: &Option<&'static Encoding>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        encoding.map(Encoding::name).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<&'static Encoding>, D::Error> {
+        let name = Option::<String>::deserialize(deserializer)?;
+        Ok(name.and_then(|name| Encoding::for_label(name.as_bytes())))
+    }
+}
+
+pub fn load_workbook<P: AsRef<Path>>(path: P, options: &CsvOptions) -> anyhow::Result<Workbook> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    let text = decode(&bytes, options.encoding);
+    let delimiter = options
+        .delimiter
+        .unwrap_or_else(|| sniff_delimiter(&text, options.quote));
+
+    let mut records = parse_records(&text, delimiter, options.quote);
+    records.retain(|record| !(record.len() == 1 && record[0].trim().is_empty()));
+
+    let has_header = options
+        .has_header
+        .unwrap_or_else(|| detect_header(&records));
+    let mut headers = if has_header && !records.is_empty() {
+        records.remove(0)
+    } else {
+        Vec::new()
+    };
+
+    let width = records
+        .iter()
+        .map(|record| record.len())
+        .max()
+        .unwrap_or(0)
+        .max(headers.len());
+    headers.resize(width, String::new());
+
+    let names = unique_names(headers.iter().enumerate().map(|(idx, name)| match name.trim() {
+        "" => format!("Column {}", idx + 1),
+        trimmed => trimmed.to_string(),
+    }));
+    let mut buffers = vec![Vec::with_capacity(records.len()); names.len()];
+
+    let mut diagnostics = SheetDiagnostics {
+        padded_rows: records.iter().filter(|record| record.len() < width).count(),
+        ..SheetDiagnostics::default()
+    };
+    for record in &records {
+        for (idx, buffer) in buffers.iter_mut().enumerate() {
+            let value = record
+                .get(idx)
+                .map(|field| parse_text(field))
+                .unwrap_or(DataValue::Empty);
+            if let DataValue::Error(error) = &value {
+                *diagnostics.error_cells.entry(error.clone()).or_default() += 1;
+            }
+            buffer.push(value);
+        }
+    }
+
+    let columns = names
+        .into_iter()
+        .zip(buffers)
+        .map(|(name, values)| typed_column(name, values))
+        .collect::<Vec<_>>();
+
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Sheet1".to_string());
+
+    Ok(Workbook {
+        sources: vec![SheetInfo {
+            name: name.clone(),
+            source: SheetSource::Sheet,
+        }],
+        sheets: vec![Arc::new(Worksheet {
+            name,
+            columns,
+            row_count: records.len(),
+            diagnostics,
+        })],
+        skipped_sheets: Vec::new(),
+    })
+}
+
+fn decode(bytes: &[u8], encoding: Option<&'static Encoding>) -> String {
+    if let Some(encoding) = encoding {
+        return encoding.decode(bytes).0.into_owned();
+    }
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return encoding
+            .decode_without_bom_handling(&bytes[bom_length..])
+            .0
+            .into_owned();
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => WINDOWS_1252.decode(bytes).0.into_owned(),
+    }
+}
+
+/// Picks the candidate delimiter that splits the leading lines into the same,
+/// largest number of fields.
+fn sniff_delimiter(text: &str, quote: char) -> char {
+    let lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(SNIFF_LINES)
+        .collect::<Vec<_>>();
+
+    let mut best = (',', 0usize, false);
+    for candidate in DELIMITER_CANDIDATES {
+        let counts = lines
+            .iter()
+            .map(|line| count_unquoted(line, candidate, quote))
+            .collect::<Vec<_>>();
+        let total = counts.iter().sum::<usize>();
+        if total == 0 {
+            continue;
+        }
+        let consistent = counts.windows(2).all(|pair| pair[0] == pair[1]);
+        let better = match (consistent, best.2) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => total > best.1,
+        };
+        if better {
+            best = (candidate, total, consistent);
+        }
+    }
+    best.0
+}
+
+fn count_unquoted(line: &str, delimiter: char, quote: char) -> usize {
+    let mut in_quotes = false;
+    let mut count = 0;
+    for ch in line.chars() {
+        if ch == quote {
+            in_quotes = !in_quotes;
+        } else if ch == delimiter && !in_quotes {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Splits RFC 4180 style text into records, honouring quoted fields that
+/// contain delimiters, doubled quotes and line breaks.
+fn parse_records(text: &str, delimiter: char, quote: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            if ch == quote {
+                if chars.peek() == Some(&quote) {
+                    field.push(quote);
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(ch);
+            }
+        } else if ch == quote && field.is_empty() {
+            in_quotes = true;
+        } else if ch == delimiter {
+            record.push(std::mem::take(&mut field));
+        } else if ch == '\n' || ch == '\r' {
+            if ch == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            record.push(std::mem::take(&mut field));
+            records.push(std::mem::take(&mut record));
+        } else {
+            field.push(ch);
+        }
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+/// Treats the first record as a header when it is made of text labels
+/// rather than numbers, dates or booleans. Labels may repeat; repeated
+/// names are made unique afterwards.
+fn detect_header(records: &[Vec<String>]) -> bool {
+    let Some(first) = records.first() else {
+        return false;
+    };
+    let mut has_label = false;
+    for cell in first {
+        match parse_text(cell) {
+            DataValue::String(_) => has_label = true,
+            DataValue::Empty => {}
+            _ => return false,
+        }
+    }
+    has_label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn repeated_labels_still_make_a_header() {
+        assert!(detect_header(&records(&[&["Date", "Amount", "Amount"], &["2024-01-01", "1", "2"]])));
+        assert!(detect_header(&records(&[&["Name", ""], &["a", "b"]])));
+        assert!(!detect_header(&records(&[&["Total", "12"], &["a", "1"]])));
+        assert!(!detect_header(&records(&[&["", ""]])));
+    }
+
+    #[test]
+    fn repeated_and_blank_header_names_are_made_unique() {
+        let path = std::env::temp_dir().join(format!("vizualizer-header-{}.csv", std::process::id()));
+        std::fs::write(&path, "Amount;Amount;;Amount\n1;2;3;4\n").expect("temp file is writable");
+        let options = CsvOptions {
+            has_header: Some(true),
+            ..CsvOptions::default()
+        };
+        let workbook = load_workbook(&path, &options);
+        std::fs::remove_file(&path).ok();
+
+        let sheet = &workbook.expect("valid file").sheets[0];
+        let names = sheet.columns.iter().map(|column| column.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["Amount", "Amount (2)", "Column 3", "Amount (3)"]);
+        assert_eq!(sheet.columns[1].value(0), DataValue::Number(2.0));
+    }
+}
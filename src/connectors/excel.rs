@@ -1,61 +1,409 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
 use std::path::Path;
-use calamine::{Data, Reader, open_workbook_auto};
-use chrono::{Duration, NaiveDate};
-
-use crate::core::data::{Column, DataType as VizDataType, DataValue};
-use crate::core::workbook::{Workbook, Worksheet};
-
-pub fn load_workbook<P: AsRef<Path>>(path: P) -> anyhow::Result<Workbook> {
-    let mut workbook = open_workbook_auto(&path)?;
-    let mut sheets = Vec::new();
-
-    for sheet_name in workbook.sheet_names().to_owned() {
-        if let Ok(range) = workbook.worksheet_range(&sheet_name) {
-            let mut rows = range.rows();
-            let headers = match rows.next() {
-                Some(header_row) => header_row
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, cell)| match cell {
-                        Data::String(text) => text.to_string(),
-                        _ => format!("Column {}", idx + 1),
-                    })
-                    .collect::<Vec<_>>(),
-                None => Vec::new(),
-            };
+use calamine::{Data, ExcelDateTime, Range, Reader, Sheets, Table, open_workbook_auto};
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
 
-            let mut columns: Vec<Column> = headers
-                .iter()
-                .map(|name| Column {
-                    name: name.clone(),
-                    data_type: VizDataType::Empty,
-                    values: Vec::new(),
-                })
-                .collect();
-
-            for row in rows {
-                for (idx, cell) in row.iter().enumerate() {
-                    if let Some(column) = columns.get_mut(idx) {
-                        column.values.push(convert_cell(cell));
+use crate::connectors::schema::{parse_iso_temporal, typed_column, unique_names};
+use crate::core::data::DataValue;
+use crate::core::workbook::{SheetDiagnostics, SheetInfo, SheetSource, Workbook, Worksheet};
+use crate::worker::Progress;
+
+/// Leading rows searched for the header row.
+const HEADER_SCAN_ROWS: usize = 20;
+/// Most rows a detected header may span.
+const MAX_HEADER_ROWS: usize = 3;
+/// Excel's sheet size: columns up to XFD and rows up to 1,048,576.
+const MAX_COLUMNS: u32 = 16_384;
+const MAX_ROWS: u32 = 1_048_576;
+
+/// Where a sheet's header sits. Unset fields are detected from the cells.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct HeaderLayout {
+    /// Rows above the header, such as title banners and blank lines, counted
+    /// from the top of the sheet even when the used range starts lower.
+    pub skip_rows: Option<usize>,
+    /// Rows making up the header; multi-row headers are flattened into one
+    /// name per column, e.g. `Sales 2023`.
+    pub header_rows: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExcelOptions {
+    /// Header layout per sheet or defined name; missing ones are detected.
+    pub headers: BTreeMap<String, HeaderLayout>,
+    /// Drops cells to the right of the last named header column instead of
+    /// loading them as unnamed columns.
+    #[serde(default)]
+    pub drop_overflow_columns: bool,
+}
+
+/// An open Excel file. Sheets, tables and defined names are listed when it
+/// opens; their cells are only parsed when a source is loaded.
+pub struct ExcelReader {
+    workbook: Sheets<BufReader<File>>,
+    options: ExcelOptions,
+}
+
+impl ExcelReader {
+    /// Opens the file and lists its sources in a workbook with no sheets
+    /// loaded yet.
+    pub fn open<P: AsRef<Path>>(path: P, options: &ExcelOptions) -> anyhow::Result<(Self, Workbook)> {
+        let mut workbook = open_workbook_auto(&path)?;
+        let sheet_names = workbook.sheet_names().to_owned();
+        let mut sources = sheet_names
+            .iter()
+            .map(|name| SheetInfo {
+                name: name.clone(),
+                source: SheetSource::Sheet,
+            })
+            .collect::<Vec<_>>();
+
+        let mut named = Vec::new();
+        if let Sheets::Xlsx(xlsx) = &mut workbook
+            && xlsx.load_tables().is_ok()
+        {
+            for sheet in &sheet_names {
+                named.extend(xlsx.table_names_in_sheet(sheet).into_iter().map(|table| {
+                    SheetSource::Table {
+                        sheet: sheet.clone(),
+                        table: table.clone(),
                     }
-                }
+                }));
+            }
+        }
+        // Print areas, filter ranges and other built-in names are skipped, as
+        // are names that do not refer to cells, such as constants. Cell
+        // references that cannot be loaded, such as several blocks, whole
+        // columns or other workbooks, are reported as skipped.
+        let mut skipped_sheets = Vec::new();
+        for (name, reference) in workbook.defined_names() {
+            if name.starts_with("_xlnm.") {
+                continue;
             }
+            if is_multi_area(reference) {
+                skipped_sheets.push((name.clone(), "multi-area names are not supported".to_string()));
+            } else if parse_reference(reference).is_some() {
+                named.push(SheetSource::DefinedName {
+                    name: name.clone(),
+                    reference: reference.trim_start_matches('=').to_string(),
+                });
+            } else if reference.contains('!') {
+                let reason = format!("unsupported reference {}", reference.trim_start_matches('='));
+                skipped_sheets.push((name.clone(), reason));
+            }
+        }
+        for source in named {
+            let base = match &source {
+                SheetSource::Table { table, .. } => table.clone(),
+                SheetSource::DefinedName { name, .. } => name.clone(),
+                SheetSource::Sheet => continue,
+            };
+            let mut name = base.clone();
+            let mut suffix = 2;
+            while sources.iter().any(|existing| existing.name == name) {
+                name = format!("{base} ({suffix})");
+                suffix += 1;
+            }
+            sources.push(SheetInfo { name, source });
+        }
 
-            for column in &mut columns {
-                column.data_type = infer_type(&column.values);
+        let reader = Self {
+            workbook,
+            options: options.clone(),
+        };
+        let workbook = Workbook {
+            sources,
+            sheets: Vec::new(),
+            skipped_sheets,
+        };
+        Ok((reader, workbook))
+    }
+
+    /// Parses one listed source into a worksheet named after it, stopping
+    /// with an error once `progress` is cancelled.
+    pub fn load(&mut self, info: &SheetInfo, progress: &Progress) -> anyhow::Result<Worksheet> {
+        let layout = self.options.headers.get(&info.name).copied().unwrap_or_default();
+        let sheet = match &info.source {
+            SheetSource::Sheet => {
+                let range = self.workbook.worksheet_range(&info.name)?;
+                read_sheet(&info.name, &range, layout, &self.options, progress)
+            }
+            SheetSource::Table { table, .. } => {
+                let Sheets::Xlsx(xlsx) = &mut self.workbook else {
+                    anyhow::bail!("tables can only be read from .xlsx files");
+                };
+                let table = xlsx.table_by_name(table)?;
+                read_table(&info.name, &table, &self.options, progress)
+            }
+            SheetSource::DefinedName { reference, .. } => {
+                let (sheet_name, (start, end)) = parse_reference(reference)
+                    .ok_or_else(|| anyhow::anyhow!("unsupported reference {reference}"))?;
+                let block = self.workbook.worksheet_range(&sheet_name)?.range(start, end);
+                read_sheet(&info.name, &block, layout, &self.options, progress)
             }
+        };
+        sheet.ok_or_else(|| anyhow::anyhow!("loading {} was cancelled", info.name))
+    }
+}
+
+/// An Excel table (ListObject), using the table's own column names and
+/// bounds.
+fn read_table(name: &str, table: &Table<Data>, options: &ExcelOptions, progress: &Progress) -> Option<Worksheet> {
+    let start_column = table.data().start().map_or(0, |(_, column)| column);
+    let rows = table.data().rows().collect::<Vec<_>>();
+    let columns = table.columns();
+    build_sheet(name, columns, &rows, columns.len(), start_column as usize, options, progress)
+}
+
+/// Zero-based (row, column) of a block's top-left and bottom-right cells.
+type CellBlock = ((u32, u32), (u32, u32));
+
+/// Whether a reference lists several blocks, as in
+/// `Sheet1!A1:B2,Sheet1!D1:E2`. Commas inside quoted sheet names do not
+/// count.
+fn is_multi_area(reference: &str) -> bool {
+    let mut quoted = false;
+    reference.chars().any(|ch| {
+        if ch == '\'' {
+            quoted = !quoted;
+        }
+        ch == ',' && !quoted
+    })
+}
+
+/// Splits `Sheet!$A$1:$C$9` (or a single cell) into the sheet name and the
+/// block it covers. References into other workbooks, written
+/// `[Book.xlsx]Sheet!A1` or `[1]Sheet!A1`, are rejected; sheet names cannot
+/// contain brackets.
+fn parse_reference(reference: &str) -> Option<(String, CellBlock)> {
+    if is_multi_area(reference) {
+        return None;
+    }
+    let (sheet, cells) = reference.trim_start_matches('=').rsplit_once('!')?;
+    let sheet = match sheet.strip_prefix('\'').and_then(|sheet| sheet.strip_suffix('\'')) {
+        Some(quoted) => quoted.replace("''", "'"),
+        None => sheet.to_string(),
+    };
+    if sheet.contains('[') {
+        return None;
+    }
+    let (start, end) = cells.split_once(':').unwrap_or((cells, cells));
+    Some((sheet, (parse_cell(start)?, parse_cell(end)?)))
+}
+
+/// Parses an `A1`-style cell, with or without `$` anchors. Cells outside
+/// Excel's sheet size are rejected.
+fn parse_cell(cell: &str) -> Option<(u32, u32)> {
+    let cell = cell.replace('$', "");
+    let split = cell.find(|ch: char| ch.is_ascii_digit())?;
+    let (letters, digits) = cell.split_at(split);
+    if letters.is_empty() || !letters.chars().all(|ch| ch.is_ascii_alphabetic()) {
+        return None;
+    }
+    let column = letters.chars().try_fold(0u32, |total, ch| {
+        total
+            .checked_mul(26)?
+            .checked_add(ch.to_ascii_uppercase() as u32 - 'A' as u32 + 1)
+    })?;
+    let row = digits.parse::<u32>().ok()?;
+    if column > MAX_COLUMNS || row > MAX_ROWS {
+        return None;
+    }
+    Some((row.checked_sub(1)?, column - 1))
+}
 
-            let row_count = columns.first().map(|col| col.len()).unwrap_or(0);
+/// Turns a sheet's used range into columns. Trailing empty rows and columns
+/// are trimmed, short rows are padded with `Empty`, and cells beyond the last
+/// named header column are kept as extra columns unless the options drop
+/// them; either way the sheet's diagnostics record it.
+fn read_sheet(
+    name: &str,
+    range: &Range<Data>,
+    layout: HeaderLayout,
+    options: &ExcelOptions,
+    progress: &Progress,
+) -> Option<Worksheet> {
+    // The used range may start anywhere, e.g. at C5 below a blank margin.
+    let (start_row, start_column) = range.start().unwrap_or((0, 0));
+    let mut all_rows = range.rows().collect::<Vec<_>>();
+    while all_rows.last().is_some_and(|row| filled_count(row) == 0) {
+        all_rows.pop();
+    }
+    let width = all_rows
+        .iter()
+        .filter_map(|row| row.iter().rposition(|cell| !matches!(cell, Data::Empty)))
+        .map(|last| last + 1)
+        .max()
+        .unwrap_or(0);
+
+    let skip_rows = layout
+        .skip_rows
+        .map(|skip| skip.saturating_sub(start_row as usize))
+        .unwrap_or_else(|| detect_header_start(&all_rows))
+        .min(all_rows.len());
+    let header_rows = layout
+        .header_rows
+        .unwrap_or_else(|| detect_header_rows(&all_rows[skip_rows..]))
+        .min(all_rows.len() - skip_rows);
+    let parts = header_parts(&all_rows[skip_rows..skip_rows + header_rows], width);
+    let rows = &all_rows[skip_rows + header_rows..];
+    build_sheet(name, &parts, rows, width, start_column as usize, options, progress)
+}
 
-            sheets.push(Worksheet {
-                name: sheet_name.to_string(),
-                columns,
-                row_count,
-            });
+/// Builds typed columns from header text and data rows, padding short rows
+/// and keeping or dropping cells beyond the last named header column.
+/// Returns `None` once `progress` is cancelled.
+fn build_sheet(
+    name: &str,
+    parts: &[String],
+    rows: &[&[Data]],
+    mut width: usize,
+    start_column: usize,
+    options: &ExcelOptions,
+    progress: &Progress,
+) -> Option<Worksheet> {
+    let mut diagnostics = SheetDiagnostics::default();
+    let named_width = parts
+        .iter()
+        .rposition(|part| !part.is_empty())
+        .map_or(0, |last| last + 1);
+    if named_width > 0 && named_width < width {
+        let extra = width - named_width;
+        if options.drop_overflow_columns {
+            width = named_width;
+            diagnostics.dropped_columns = extra;
+        } else {
+            diagnostics.extra_columns = extra;
         }
     }
+    diagnostics.padded_rows = rows.iter().filter(|row| row.len() < width).count();
+
+    let names = column_names(&parts[..width.min(parts.len())], start_column);
+    let mut buffers = vec![Vec::with_capacity(rows.len()); names.len()];
+    for (index, row) in rows.iter().enumerate() {
+        if !progress.report_rows(index, rows.len()) {
+            return None;
+        }
+        for (idx, buffer) in buffers.iter_mut().enumerate() {
+            let value = row.get(idx).map(convert_cell).unwrap_or(DataValue::Empty);
+            if let DataValue::Error(error) = &value {
+                *diagnostics.error_cells.entry(error.clone()).or_default() += 1;
+            }
+            buffer.push(value);
+        }
+    }
+
+    let columns = names
+        .into_iter()
+        .zip(buffers)
+        .map(|(name, values)| typed_column(name, values))
+        .collect::<Vec<_>>();
+
+    Some(Worksheet {
+        name: name.to_string(),
+        columns,
+        row_count: rows.len(),
+        diagnostics,
+    })
+}
+
+fn filled_count(row: &[Data]) -> usize {
+    row.iter().filter(|cell| !matches!(cell, Data::Empty)).count()
+}
 
-    Ok(Workbook { sheets })
+/// The first row filling at least two cells (one for single-column sheets),
+/// which skips blank rows and single-cell title banners.
+fn detect_header_start(rows: &[&[Data]]) -> usize {
+    let scanned = &rows[..rows.len().min(HEADER_SCAN_ROWS)];
+    let width = scanned.iter().map(|row| filled_count(row)).max().unwrap_or(0);
+    scanned
+        .iter()
+        .position(|row| filled_count(row) >= width.min(2))
+        .unwrap_or(0)
+}
+
+/// Counts header rows from the top of `rows`. A following row joins the
+/// header when it fills every gap left by merged cells above it and reads as
+/// labels: either the row above is sparse, or its cells look like labels
+/// over the first data row.
+fn detect_header_rows(rows: &[&[Data]]) -> usize {
+    let mut count = 1;
+    while count < MAX_HEADER_ROWS && count + 1 < rows.len() {
+        let (above, row, body) = (rows[count - 1], rows[count], rows[count + 1]);
+        let filled = |row: &[Data], idx: usize| row.get(idx).is_some_and(|cell| !matches!(cell, Data::Empty));
+        let gaps = (0..row.len())
+            .filter(|&idx| !filled(above, idx))
+            .collect::<Vec<_>>();
+        let fills_gaps = !gaps.is_empty() && gaps.iter().all(|&idx| filled(row, idx));
+        let sparse_above = filled_count(above) * 2 <= filled_count(row);
+        let labels = row
+            .iter()
+            .zip(body.iter())
+            .any(|(cell, below)| looks_like_label(cell, below));
+        if !(fills_gaps && (sparse_above || labels)) {
+            break;
+        }
+        count += 1;
+    }
+    count.min(rows.len())
+}
+
+/// Text over a non-text value, or a year over a number that is not a year.
+fn looks_like_label(cell: &Data, below: &Data) -> bool {
+    let is_year = |value: f64| value.fract() == 0.0 && (1900.0..=2100.0).contains(&value);
+    match (convert_cell(cell), convert_cell(below)) {
+        (DataValue::String(_), below) => !matches!(below, DataValue::String(_) | DataValue::Empty),
+        (DataValue::Number(value), DataValue::Number(below)) => is_year(value) && !is_year(below),
+        _ => false,
+    }
+}
+
+/// The header text of each of the first `width` columns: header rows are
+/// joined top to bottom, with cells merged across columns carried to the
+/// right in all but the last row. Numbers and dates are used as written.
+fn header_parts(header: &[&[Data]], width: usize) -> Vec<String> {
+    let mut parts = vec![Vec::<String>::new(); width];
+    for (row_index, row) in header.iter().enumerate() {
+        let carry = row_index + 1 < header.len();
+        let mut previous = String::new();
+        for (idx, column_parts) in parts.iter_mut().enumerate() {
+            let text = row
+                .get(idx)
+                .map(|cell| convert_cell(cell).display_string().trim().to_string())
+                .unwrap_or_default();
+            let text = if text.is_empty() && carry { previous.clone() } else { text };
+            if !text.is_empty() && column_parts.last() != Some(&text) {
+                column_parts.push(text.clone());
+            }
+            previous = text;
+        }
+    }
+
+    parts.into_iter().map(|column_parts| column_parts.join(" ")).collect()
+}
+
+/// Blank names become `Column C` after the column's sheet letter, and
+/// repeated names get a ` (2)`, ` (3)` suffix.
+fn column_names(parts: &[String], start_column: usize) -> Vec<String> {
+    unique_names(parts.iter().enumerate().map(|(idx, part)| match part.as_str() {
+        "" => format!("Column {}", column_letters(start_column + idx)),
+        name => name.to_string(),
+    }))
+}
+
+/// Spreadsheet letters for a zero-based column index: 0 is `A`, 26 is `AA`.
+fn column_letters(index: usize) -> String {
+    let mut letters = Vec::new();
+    let mut remaining = index + 1;
+    while remaining > 0 {
+        remaining -= 1;
+        letters.push(char::from(b'A' + (remaining % 26) as u8));
+        remaining /= 26;
+    }
+    letters.iter().rev().collect()
 }
 
 fn convert_cell(cell: &Data) -> DataValue {
@@ -64,50 +412,206 @@ fn convert_cell(cell: &Data) -> DataValue {
         Data::Float(value) => DataValue::Number(*value),
         Data::Int(value) => DataValue::Number(*value as f64),
         Data::Bool(value) => DataValue::Boolean(*value),
-        Data::DateTime(value) => {
-            let serial = value.as_f64();
-            DataValue::Date(excel_serial_to_date(serial))
+        Data::DateTime(value) => convert_excel_datetime(value),
+        Data::DateTimeIso(value) => {
+            parse_iso_temporal(value).unwrap_or(DataValue::String(value.clone()))
         }
-        Data::DateTimeIso(value) => parse_iso_date(value).unwrap_or(DataValue::String(value.clone())),
+        Data::Error(error) => DataValue::Error(error.to_string()),
         Data::Empty => DataValue::Empty,
         _ => DataValue::String(cell.to_string()),
     }
 }
 
-fn excel_serial_to_date(value: f64) -> NaiveDate {
-    let base = NaiveDate::from_ymd_opt(1899, 12, 30).unwrap();
-    base + Duration::days(value.floor() as i64)
+/// Keeps the time of day: serials with a fraction become `DateTime`s,
+/// serials below one (time-only formats) become `Time`s, and duration
+/// formats such as `[h]:mm` become `Duration`s.
+fn convert_excel_datetime(value: &ExcelDateTime) -> DataValue {
+    if value.is_duration() {
+        return DataValue::Duration(value.as_f64() * 86_400.0);
+    }
+    let Some(datetime) = value.as_datetime() else {
+        return DataValue::Number(value.as_f64());
+    };
+    if value.as_f64() < 1.0 {
+        DataValue::Time(datetime.time())
+    } else if datetime.time() == NaiveTime::MIN {
+        DataValue::Date(datetime.date())
+    } else {
+        DataValue::DateTime(datetime)
+    }
 }
 
-fn parse_iso_date(value: &str) -> Option<DataValue> {
-    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().map(DataValue::Date)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn infer_type(values: &[DataValue]) -> VizDataType {
-    let mut has_string = false;
-    let mut has_number = false;
-    let mut has_date = false;
-    let mut has_bool = false;
+    fn text(value: &str) -> Data {
+        Data::String(value.to_string())
+    }
 
-    for value in values {
-        match value {
-            DataValue::String(_) => has_string = true,
-            DataValue::Number(_) => has_number = true,
-            DataValue::Date(_) => has_date = true,
-            DataValue::Boolean(_) => has_bool = true,
-            DataValue::Empty => {}
+    /// A used range starting at `start`, as wide as its widest row.
+    fn range(start: (u32, u32), rows: &[&[Data]]) -> Range<Data> {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(1) as u32;
+        let end = (start.0 + rows.len() as u32 - 1, start.1 + width - 1);
+        let mut range = Range::new(start, end);
+        for (row_index, row) in rows.iter().enumerate() {
+            for (column_index, cell) in row.iter().enumerate() {
+                range.set_value((start.0 + row_index as u32, start.1 + column_index as u32), cell.clone());
+            }
         }
+        range
     }
 
-    if has_string {
-        VizDataType::String
-    } else if has_date {
-        VizDataType::Date
-    } else if has_bool && !has_number {
-        VizDataType::Boolean
-    } else if has_number {
-        VizDataType::Number
-    } else {
-        VizDataType::Empty
+    fn load(range: &Range<Data>, layout: HeaderLayout, options: &ExcelOptions) -> Worksheet {
+        read_sheet("Sheet", range, layout, options, &Progress::default()).expect("not cancelled")
+    }
+
+    fn names(sheet: &Worksheet) -> Vec<&str> {
+        sheet.columns.iter().map(|column| column.name.as_str()).collect()
+    }
+
+    fn block(sheet: &str, start: (u32, u32), end: (u32, u32)) -> Option<(String, CellBlock)> {
+        Some((sheet.to_string(), (start, end)))
+    }
+
+    #[test]
+    fn references_split_into_sheet_and_block() {
+        assert_eq!(parse_reference("=Sales!$A$1:$C$9"), block("Sales", (0, 0), (8, 2)));
+        assert_eq!(parse_reference("'Q1, 2024''s'!B2"), block("Q1, 2024's", (1, 1), (1, 1)));
+        assert_eq!(parse_reference("Sheet1!AA10:AB11"), block("Sheet1", (9, 26), (10, 27)));
+    }
+
+    #[test]
+    fn multi_area_references_are_rejected() {
+        assert!(is_multi_area("Sheet1!A1:B2,Sheet1!D1:E2"));
+        assert!(!is_multi_area("'Q1, 2024'!A1:B2"));
+        assert_eq!(parse_reference("Sheet1!A1:B2,Sheet1!D1:E2"), None);
+    }
+
+    #[test]
+    fn malformed_cells_are_rejected_without_overflowing() {
+        assert_eq!(parse_cell("A0"), None);
+        assert_eq!(parse_cell("12"), None);
+        assert_eq!(parse_cell("A1B"), None);
+        assert_eq!(parse_cell("ZZZZZZZZZZZZZZZZ1"), None);
+        assert_eq!(parse_cell("$XFD$1048576"), Some((1_048_575, 16_383)));
+        assert_eq!(parse_cell("XFE1"), None);
+        assert_eq!(parse_cell("A1048577"), None);
+        assert_eq!(parse_cell("$ZZZZ$99999999"), None);
+    }
+
+    #[test]
+    fn whole_column_row_and_external_references_are_rejected() {
+        assert_eq!(parse_reference("Sheet1!$A:$D"), None);
+        assert_eq!(parse_reference("Sheet1!$1:$3"), None);
+        assert_eq!(parse_reference("[1]Sheet1!A1:B2"), None);
+        assert_eq!(parse_reference("'[Budget.xlsx]Sheet 1'!A1:B2"), None);
+    }
+
+    #[test]
+    fn used_ranges_away_from_a1_lose_trailing_blank_rows_and_columns() {
+        let range = range(
+            (4, 2),
+            &[
+                &[text("Region"), text("Amount"), Data::Empty, Data::Empty],
+                &[text("North"), Data::Float(1.0), text("note"), Data::Empty],
+                &[text("South"), Data::Float(2.0), Data::Empty, Data::Empty],
+                &[Data::Empty, Data::Empty, Data::Empty, Data::Empty],
+                &[Data::Empty, Data::Empty, Data::Empty, Data::Empty],
+            ],
+        );
+        let sheet = load(&range, HeaderLayout::default(), &ExcelOptions::default());
+        // The unnamed third column is named after its sheet letter, E.
+        assert_eq!(names(&sheet), ["Region", "Amount", "Column E"]);
+        assert_eq!(sheet.row_count, 2);
+        assert_eq!(sheet.columns[2].value(0), DataValue::String("note".to_string()));
+        assert_eq!(sheet.diagnostics.extra_columns, 1);
+
+        let options = ExcelOptions {
+            drop_overflow_columns: true,
+            ..ExcelOptions::default()
+        };
+        let sheet = load(&range, HeaderLayout::default(), &options);
+        assert_eq!(names(&sheet), ["Region", "Amount"]);
+        assert_eq!(sheet.diagnostics.dropped_columns, 1);
+        assert_eq!(sheet.diagnostics.extra_columns, 0);
+    }
+
+    #[test]
+    fn skip_rows_count_from_the_top_of_the_sheet() {
+        let range = range(
+            (2, 0),
+            &[
+                &[text("Quarterly report")],
+                &[text("Region"), text("Amount")],
+                &[text("North"), Data::Float(1.0)],
+            ],
+        );
+        let layout = HeaderLayout {
+            skip_rows: Some(3),
+            header_rows: Some(1),
+        };
+        let sheet = load(&range, layout, &ExcelOptions::default());
+        assert_eq!(names(&sheet), ["Region", "Amount"]);
+        assert_eq!(sheet.row_count, 1);
+    }
+
+    #[test]
+    fn short_rows_are_padded_with_empty_cells() {
+        let parts = ["Region".to_string(), "Amount".to_string()];
+        let full = [text("North"), Data::Float(1.0)];
+        let short = [text("South")];
+        let error = [text("East"), Data::Error(calamine::CellErrorType::NA)];
+        let rows: [&[Data]; 3] = [&full, &short, &error];
+        let sheet = build_sheet("Table", &parts, &rows, 2, 0, &ExcelOptions::default(), &Progress::default())
+            .expect("not cancelled");
+        assert_eq!(sheet.diagnostics.padded_rows, 1);
+        assert_eq!(sheet.columns[1].value(1), DataValue::Empty);
+        assert_eq!(sheet.columns[1].value(2), DataValue::Error("#N/A".to_string()));
+        assert_eq!(sheet.diagnostics.error_cells.get("#N/A"), Some(&1));
+    }
+
+    #[test]
+    fn title_banners_and_blank_rows_above_the_header_are_skipped() {
+        let rows: [&[Data]; 5] = [
+            &[text("Sales by region"), Data::Empty, Data::Empty],
+            &[Data::Empty, Data::Empty, Data::Empty],
+            &[text("Region"), text("Amount"), text("Units")],
+            &[text("North"), Data::Float(1.0), Data::Float(3.0)],
+            &[text("South"), Data::Float(2.0), Data::Float(4.0)],
+        ];
+        assert_eq!(detect_header_start(&rows), 2);
+        assert_eq!(detect_header_rows(&rows[2..]), 1);
+        // A single-column sheet starts at its first filled row.
+        let single: [&[Data]; 3] = [&[Data::Empty], &[text("Name")], &[text("Ada")]];
+        assert_eq!(detect_header_start(&single), 1);
+    }
+
+    #[test]
+    fn multi_row_headers_are_flattened_per_column() {
+        let rows: [&[Data]; 3] = [
+            &[text("Region"), text("Sales"), Data::Empty],
+            &[Data::Empty, Data::Float(2023.0), Data::Float(2024.0)],
+            &[text("North"), Data::Float(150.0), Data::Float(170.0)],
+        ];
+        assert_eq!(detect_header_rows(&rows), 2);
+        assert_eq!(header_parts(&rows[..2], 3), ["Region", "Sales 2023", "Sales 2024"]);
+
+        let sheet = load(&range((0, 0), &rows), HeaderLayout::default(), &ExcelOptions::default());
+        assert_eq!(names(&sheet), ["Region", "Sales 2023", "Sales 2024"]);
+        assert_eq!(sheet.row_count, 1);
+    }
+
+    #[test]
+    fn a_data_row_below_a_full_header_is_not_taken_for_a_header() {
+        let rows: [&[Data]; 3] = [
+            &[text("Region"), text("Amount")],
+            &[text("North"), Data::Float(2023.0)],
+            &[text("South"), Data::Float(2024.0)],
+        ];
+        assert_eq!(detect_header_rows(&rows), 1);
+        // Repeated labels in one column are kept once.
+        let header: [&[Data]; 2] = [&[text("Sales"), text("Sales")], &[text("Sales"), text("Units")]];
+        assert_eq!(header_parts(&header, 2), ["Sales", "Sales Units"]);
     }
 }
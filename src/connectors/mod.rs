@@ -1 +1,103 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::connectors::csv::CsvOptions;
+use crate::connectors::excel::{ExcelOptions, ExcelReader};
+use crate::core::workbook::{SheetInfo, Workbook, Worksheet};
+use crate::worker::Progress;
+
+pub mod csv;
 pub mod excel;
+pub mod schema;
+
+/// Extensions handled by the delimited-text connector; everything else is
+/// handed to the Excel connector.
+pub const DELIMITED_EXTENSIONS: [&str; 4] = ["csv", "tsv", "tab", "txt"];
+pub const EXCEL_EXTENSIONS: [&str; 4] = ["xlsx", "xlsm", "xlsb", "xls"];
+
+/// An opened data file. Delimited files are parsed whole when opened; Excel
+/// sources are parsed the first time they are loaded and then kept.
+pub struct DataFile {
+    workbook: Workbook,
+    excel: Option<Arc<Mutex<ExcelReader>>>,
+}
+
+/// Parses one Excel source, possibly on another thread, while the
+/// `DataFile` stays with the UI.
+pub struct SheetLoader {
+    reader: Arc<Mutex<ExcelReader>>,
+    info: SheetInfo,
+}
+
+impl SheetLoader {
+    pub fn load(self, progress: &Progress) -> anyhow::Result<Worksheet> {
+        let mut reader = self
+            .reader
+            .lock()
+            .map_err(|_| anyhow::anyhow!("an earlier load of this workbook failed"))?;
+        // The load may have been cancelled while waiting for another one.
+        if progress.is_cancelled() {
+            anyhow::bail!("loading {} was cancelled", self.info.name);
+        }
+        reader.load(&self.info, progress)
+    }
+}
+
+impl DataFile {
+    pub fn open<P: AsRef<Path>>(path: P, excel: &ExcelOptions, csv: &CsvOptions) -> anyhow::Result<Self> {
+        if is_delimited(path.as_ref()) {
+            return Ok(Self {
+                workbook: csv::load_workbook(path, csv)?,
+                excel: None,
+            });
+        }
+        let (reader, workbook) = ExcelReader::open(path, excel)?;
+        Ok(Self {
+            workbook,
+            excel: Some(Arc::new(Mutex::new(reader))),
+        })
+    }
+
+    pub fn workbook(&self) -> &Workbook {
+        &self.workbook
+    }
+
+    /// Whether `name` still has to be parsed before it can be shown.
+    pub fn needs_loading(&self, name: &str) -> bool {
+        self.workbook.sources.iter().any(|source| source.name == name)
+            && self.workbook.sheet(name).is_none()
+            && !self.workbook.is_skipped(name)
+    }
+
+    /// A loader for the source called `name`, unless it is already loaded.
+    pub fn sheet_loader(&self, name: &str) -> Option<SheetLoader> {
+        if !self.needs_loading(name) {
+            return None;
+        }
+        Some(SheetLoader {
+            reader: Arc::clone(self.excel.as_ref()?),
+            info: self.workbook.sources.iter().find(|source| source.name == name)?.clone(),
+        })
+    }
+
+    /// Keeps a loaded source; failures are recorded as skipped sheets.
+    pub fn insert_sheet(&mut self, name: &str, loaded: anyhow::Result<Worksheet>) {
+        match loaded {
+            Ok(sheet) => self.workbook.sheets.push(Arc::new(sheet)),
+            Err(error) => self
+                .workbook
+                .skipped_sheets
+                .push((name.to_string(), error.to_string())),
+        }
+    }
+}
+
+pub fn is_delimited(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            DELIMITED_EXTENSIONS
+                .iter()
+                .any(|candidate| extension.eq_ignore_ascii_case(candidate))
+        })
+}
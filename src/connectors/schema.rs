@@ -0,0 +1,624 @@
+use std::collections::HashSet;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::core::column::Column;
+use crate::core::data::{DataType, DataValue};
+use crate::core::workbook::Worksheet;
+use crate::worker::Progress;
+
+/// Builds a column of the inferred type, promoting plain dates in timestamp
+/// columns to midnight `DateTime`s so every value shares one representation.
+pub fn typed_column(name: String, mut values: Vec<DataValue>) -> Column {
+    let data_type = infer_type(&values);
+    if data_type == DataType::DateTime {
+        for value in &mut values {
+            if let Some(datetime) = value.as_datetime() {
+                *value = DataValue::DateTime(datetime);
+            }
+        }
+    }
+    Column::new(name, data_type, values)
+}
+
+/// Suffixes repeated column names with ` (2)`, ` (3)` and so on, so each
+/// column can be looked up by its name.
+pub fn unique_names(names: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    names
+        .into_iter()
+        .map(|base| {
+            let mut name = base.clone();
+            let mut suffix = 2;
+            while !seen.insert(name.clone()) {
+                name = format!("{base} ({suffix})");
+                suffix += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+pub fn infer_type(values: &[DataValue]) -> DataType {
+    let mut has_string = false;
+    let mut has_number = false;
+    let mut has_date = false;
+    let mut has_datetime = false;
+    let mut has_time = false;
+    let mut has_duration = false;
+    let mut has_bool = false;
+
+    for value in values {
+        match value {
+            DataValue::String(_) => has_string = true,
+            DataValue::Number(_) => has_number = true,
+            DataValue::Date(_) => has_date = true,
+            DataValue::DateTime(_) => has_datetime = true,
+            DataValue::Time(_) => has_time = true,
+            DataValue::Duration(_) => has_duration = true,
+            DataValue::Boolean(_) => has_bool = true,
+            DataValue::Error(_) | DataValue::Empty => {}
+        }
+    }
+
+    if has_string {
+        DataType::String
+    } else if has_datetime {
+        DataType::DateTime
+    } else if has_date {
+        DataType::Date
+    } else if has_time {
+        DataType::Time
+    } else if has_duration {
+        DataType::Duration
+    } else if has_bool && !has_number {
+        DataType::Boolean
+    } else if has_number {
+        DataType::Number
+    } else {
+        DataType::Empty
+    }
+}
+
+/// Spreadsheet error literals, as found in files exported from Excel.
+const CELL_ERRORS: [&str; 8] = [
+    "#DIV/0!", "#N/A", "#NAME?", "#NULL!", "#NUM!", "#REF!", "#VALUE!", "#GETTING_DATA",
+];
+
+/// Converts raw text (e.g. a CSV field) into the most specific value it represents.
+pub fn parse_text(text: &str) -> DataValue {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return DataValue::Empty;
+    }
+    if let Ok(number) = trimmed.parse::<f64>()
+        && number.is_finite()
+    {
+        return DataValue::Number(number);
+    }
+    if let Some(value) = parse_iso_temporal(trimmed) {
+        return value;
+    }
+    if CELL_ERRORS.contains(&trimmed) {
+        return DataValue::Error(trimmed.to_string());
+    }
+    match trimmed.to_ascii_lowercase().as_str() {
+        "true" => DataValue::Boolean(true),
+        "false" => DataValue::Boolean(false),
+        _ => DataValue::String(text.to_string()),
+    }
+}
+
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+const TIME_FORMATS: [&str; 4] = ["%H:%M:%S%.f", "%H:%M", "%I:%M:%S %p", "%I:%M %p"];
+
+/// Parses ISO 8601 dates, timestamps (`T` or space separated) and times of
+/// day. A timestamp at exactly midnight still becomes a `DateTime`.
+pub fn parse_iso_temporal(value: &str) -> Option<DataValue> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(DataValue::Date(date));
+    }
+    if let Some(datetime) = DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    {
+        return Some(DataValue::DateTime(datetime));
+    }
+    TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(value, format).ok())
+        .map(DataValue::Time)
+}
+
+/// Settings for the tolerant inference run on loaded sheets.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct InferenceOptions {
+    /// Largest share of a column's non-empty cells that may fail to parse
+    /// as the inferred type; those cells become empty.
+    pub error_threshold: f64,
+}
+
+impl Default for InferenceOptions {
+    fn default() -> Self {
+        Self {
+            error_threshold: 0.05,
+        }
+    }
+}
+
+/// A user-chosen type for one column, kept in the project.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TypeOverride {
+    pub sheet: String,
+    pub column: String,
+    pub data_type: DataType,
+}
+
+/// Returns a copy of `sheet` with each column converted to its overridden
+/// or inferred type. Connectors keep raw cell values, so this can be re-run
+/// whenever the options or overrides change. Cells that do not fit their
+/// column's type become empty and are counted in the sheet's diagnostics.
+/// Returns `None` once `progress` is cancelled.
+pub fn apply_types(
+    sheet: &Worksheet,
+    options: &InferenceOptions,
+    overrides: &[TypeOverride],
+    progress: &Progress,
+) -> Option<Worksheet> {
+    let mut diagnostics = sheet.diagnostics.clone();
+    let column_count = sheet.columns.len().max(1) as f32;
+    let mut columns = Vec::with_capacity(sheet.columns.len());
+    for (index, column) in sheet.columns.iter().enumerate() {
+        let part = progress.part(index as f32 / column_count, (index + 1) as f32 / column_count);
+        let raw = column.iter().collect::<Vec<_>>();
+        let parsers = ColumnParsers::detect(&raw);
+        let data_type = overrides
+            .iter()
+            .find(|item| item.sheet == sheet.name && item.column == column.name)
+            .map(|item| item.data_type.clone())
+            .unwrap_or_else(|| parsers.infer(&raw, options));
+        let mut coerced = 0;
+        let mut values = Vec::with_capacity(raw.len());
+        for (row, value) in raw.iter().enumerate() {
+            if !part.report_rows(row, raw.len()) {
+                return None;
+            }
+            values.push(parsers.convert(value, &data_type).unwrap_or_else(|| {
+                coerced += 1;
+                DataValue::Empty
+            }));
+        }
+        if coerced > 0 {
+            diagnostics.coerced_values.insert(column.name.clone(), coerced);
+        }
+        columns.push(Column::new(column.name.clone(), data_type, values));
+    }
+    Some(Worksheet {
+        name: sheet.name.clone(),
+        columns,
+        row_count: sheet.row_count,
+        diagnostics,
+    })
+}
+
+/// Which character separates decimals; the other common separators are
+/// accepted as thousands separators.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NumberStyle {
+    DecimalPoint,
+    DecimalComma,
+}
+
+const DATE_FORMATS: [&str; 12] = [
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%m/%d/%Y",
+    "%d/%m/%Y",
+    "%d.%m.%Y",
+    "%m-%d-%Y",
+    "%d-%m-%Y",
+    "%b %d, %Y",
+    "%B %d, %Y",
+    "%d %b %Y",
+    "%d %B %Y",
+    "%b %d %Y",
+];
+
+const TIME_SUFFIXES: [&str; 6] = [
+    " %H:%M:%S%.f",
+    " %H:%M",
+    "T%H:%M:%S%.f",
+    "T%H:%M",
+    " %I:%M:%S %p",
+    " %I:%M %p",
+];
+
+/// Strings sampled per column when choosing a number style and date format.
+const SAMPLE_SIZE: usize = 1000;
+
+/// Per-column text parsing choices. Ambiguous text such as `03/04/2024` or
+/// `1.234` is read the way that parses most of the column's text.
+struct ColumnParsers {
+    number_style: NumberStyle,
+    date_format: Option<&'static str>,
+}
+
+impl ColumnParsers {
+    fn detect(values: &[DataValue]) -> Self {
+        let texts = values
+            .iter()
+            .filter_map(|value| match value {
+                DataValue::String(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .take(SAMPLE_SIZE)
+            .collect::<Vec<_>>();
+        let successes = |parse: &dyn Fn(&str) -> bool| texts.iter().filter(|text| parse(text)).count();
+
+        let comma = successes(&|text| parse_number(text, NumberStyle::DecimalComma).is_some());
+        let point = successes(&|text| parse_number(text, NumberStyle::DecimalPoint).is_some());
+        let number_style = if comma > point {
+            NumberStyle::DecimalComma
+        } else {
+            NumberStyle::DecimalPoint
+        };
+
+        // `max_by_key` keeps the last maximum, so scan in reverse to prefer
+        // earlier formats on ties.
+        let date_format = DATE_FORMATS
+            .iter()
+            .rev()
+            .map(|format| (format, successes(&|text| parse_date(text, format).is_some())))
+            .filter(|(_, count)| *count > 0)
+            .max_by_key(|(_, count)| *count)
+            .map(|(format, _)| *format);
+
+        Self {
+            number_style,
+            date_format,
+        }
+    }
+
+    /// Picks the most specific type that all but `error_threshold` of the
+    /// non-empty values convert to, falling back to `String`.
+    fn infer(&self, values: &[DataValue], options: &InferenceOptions) -> DataType {
+        let present = values
+            .iter()
+            .filter(|value| !value.is_null())
+            .collect::<Vec<_>>();
+        if present.is_empty() {
+            return DataType::Empty;
+        }
+        let allowed_failures = (present.len() as f64 * options.error_threshold.max(0.0)).floor() as usize;
+        let fits = |data_type: &DataType| {
+            present
+                .iter()
+                .filter(|value| self.convert(value, data_type).is_none())
+                .nth(allowed_failures)
+                .is_none()
+        };
+
+        if fits(&DataType::Boolean) {
+            return DataType::Boolean;
+        }
+        if fits(&DataType::Number) {
+            return DataType::Number;
+        }
+        if fits(&DataType::DateTime) {
+            let has_time = present.iter().any(|value| {
+                matches!(
+                    self.convert(value, &DataType::DateTime),
+                    Some(DataValue::DateTime(datetime)) if datetime.time() != NaiveTime::MIN
+                )
+            });
+            return if has_time {
+                DataType::DateTime
+            } else {
+                DataType::Date
+            };
+        }
+        [DataType::Time, DataType::Duration]
+            .into_iter()
+            .find(|data_type| fits(data_type))
+            .unwrap_or(DataType::String)
+    }
+
+    /// Converts one value to `data_type`, or `None` when it does not fit.
+    fn convert(&self, value: &DataValue, data_type: &DataType) -> Option<DataValue> {
+        let text = match value {
+            DataValue::Empty | DataValue::Error(_) => return Some(value.clone()),
+            DataValue::String(text) => Some(text.trim()),
+            _ => None,
+        };
+        match data_type {
+            DataType::String => Some(DataValue::String(value.display_string())),
+            DataType::Number => match value {
+                DataValue::Number(_) => Some(value.clone()),
+                _ => parse_number(text?, self.number_style).map(DataValue::Number),
+            },
+            DataType::Boolean => match value {
+                DataValue::Boolean(_) => Some(value.clone()),
+                _ => parse_boolean(text?).map(DataValue::Boolean),
+            },
+            DataType::Date => self.temporal(value, text)?.as_date().map(DataValue::Date),
+            DataType::DateTime => self.temporal(value, text)?.as_datetime().map(DataValue::DateTime),
+            DataType::Time => match value {
+                DataValue::Time(_) => Some(value.clone()),
+                DataValue::DateTime(datetime) => Some(DataValue::Time(datetime.time())),
+                _ => TIME_FORMATS
+                    .iter()
+                    .find_map(|format| NaiveTime::parse_from_str(text?, format).ok())
+                    .map(DataValue::Time),
+            },
+            DataType::Duration => match value {
+                DataValue::Duration(_) => Some(value.clone()),
+                DataValue::Time(time) => Some(DataValue::Duration(
+                    (*time - NaiveTime::MIN).num_milliseconds() as f64 / 1000.0,
+                )),
+                _ => None,
+            },
+            DataType::Empty => Some(DataValue::Empty),
+        }
+    }
+
+    /// Dates, timestamps, date text, and numbers read as Excel serial dates,
+    /// as in a date column formatted as numbers.
+    fn temporal(&self, value: &DataValue, text: Option<&str>) -> Option<DataValue> {
+        match value {
+            DataValue::Date(_) | DataValue::DateTime(_) => Some(value.clone()),
+            DataValue::Number(serial) => excel_serial_datetime(*serial).map(DataValue::DateTime),
+            _ => {
+                let text = text?;
+                parse_iso_temporal(text)
+                    .filter(|value| value.as_datetime().is_some())
+                    .or_else(|| parse_date(text, self.date_format?))
+            }
+        }
+    }
+}
+
+/// An Excel serial date (days since 1899-12-30, the fraction being the time
+/// of day) from 1900-01-01 through 9999-12-31. Serials before March 1900
+/// allow for Excel counting a 29 February 1900 that never existed.
+fn excel_serial_datetime(serial: f64) -> Option<NaiveDateTime> {
+    if !(1.0..2_958_466.0).contains(&serial) {
+        return None;
+    }
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, if serial < 60.0 { 31 } else { 30 })?;
+    let milliseconds = (serial * 86_400_000.0).round() as i64;
+    epoch
+        .and_time(NaiveTime::MIN)
+        .checked_add_signed(chrono::Duration::milliseconds(milliseconds))
+}
+
+/// Parses a date in `format`, optionally followed by a time of day.
+fn parse_date(text: &str, format: &str) -> Option<DataValue> {
+    if let Ok(date) = NaiveDate::parse_from_str(text, format) {
+        return Some(DataValue::Date(date));
+    }
+    TIME_SUFFIXES
+        .iter()
+        .find_map(|suffix| NaiveDateTime::parse_from_str(text, &format!("{format}{suffix}")).ok())
+        .map(DataValue::DateTime)
+}
+
+fn parse_boolean(text: &str) -> Option<bool> {
+    match text.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "y" => Some(true),
+        "false" | "no" | "n" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses numbers as people write them: thousands separators, currency
+/// symbols, percentages (`12%` is 0.12) and accounting negatives (`(5)`).
+fn parse_number(text: &str, style: NumberStyle) -> Option<f64> {
+    const CURRENCY: [char; 4] = ['$', '€', '£', '¥'];
+    let mut body = text.trim();
+    let mut negative = false;
+    if let Some(inner) = body.strip_prefix('(').and_then(|inner| inner.strip_suffix(')')) {
+        negative = true;
+        body = inner.trim();
+    }
+    let percent = body.ends_with('%');
+    body = body.trim_end_matches('%').trim_end();
+    for _ in 0..2 {
+        if let Some(rest) = body.strip_prefix('-') {
+            negative = !negative;
+            body = rest.trim_start();
+        } else if let Some(rest) = body.strip_prefix('+') {
+            body = rest.trim_start();
+        }
+        body = body
+            .trim_start_matches(CURRENCY)
+            .trim_end_matches(CURRENCY)
+            .trim();
+    }
+    if body.is_empty() || !body.starts_with(|ch: char| ch.is_ascii_digit() || ch == '.' || ch == ',') {
+        return None;
+    }
+
+    let (decimal, separators): (char, &[char]) = match style {
+        NumberStyle::DecimalPoint => ('.', &[',', ' ', '\'', '\u{a0}']),
+        NumberStyle::DecimalComma => (',', &['.', ' ', '\'', '\u{a0}']),
+    };
+    let plain = body.parse::<f64>().ok().filter(|_| style == NumberStyle::DecimalPoint);
+    let number = match plain {
+        Some(number) => number,
+        None => {
+            let (integer, fraction) = match body.split_once(decimal) {
+                Some((integer, fraction)) => (integer, Some(fraction)),
+                None => (body, None),
+            };
+            let groups = integer.split(separators).collect::<Vec<_>>();
+            let grouped_ok = groups.len() == 1
+                || (!groups[0].is_empty()
+                    && groups[0].len() <= 3
+                    && groups[1..].iter().all(|group| group.len() == 3));
+            let digits = |part: &str| part.chars().all(|ch| ch.is_ascii_digit());
+            if !grouped_ok
+                || !groups.iter().all(|group| digits(group))
+                || !fraction.is_none_or(|fraction| !fraction.is_empty() && digits(fraction))
+            {
+                return None;
+            }
+            let normalized = format!("{}.{}", groups.concat(), fraction.unwrap_or("0"));
+            normalized.parse::<f64>().ok()?
+        }
+    };
+    if !number.is_finite() {
+        return None;
+    }
+    let number = if percent { number / 100.0 } else { number };
+    Some(if negative { -number } else { number })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::workbook::SheetDiagnostics;
+
+    fn texts(values: &[&str]) -> Vec<DataValue> {
+        values.iter().map(|value| DataValue::String(value.to_string())).collect()
+    }
+
+    fn sheet(columns: Vec<(&str, Vec<DataValue>)>) -> Worksheet {
+        Worksheet {
+            name: "Sheet".to_string(),
+            row_count: columns.first().map_or(0, |(_, values)| values.len()),
+            columns: columns
+                .into_iter()
+                .map(|(name, values)| Column::new(name.to_string(), DataType::String, values))
+                .collect(),
+            diagnostics: SheetDiagnostics::default(),
+        }
+    }
+
+    fn typed(sheet: &Worksheet, options: &InferenceOptions, overrides: &[TypeOverride]) -> Worksheet {
+        apply_types(sheet, options, overrides, &Progress::default()).expect("not cancelled")
+    }
+
+    #[test]
+    fn separators_are_read_in_the_given_style() {
+        assert_eq!(parse_number("1,234.5", NumberStyle::DecimalPoint), Some(1234.5));
+        assert_eq!(parse_number("1.234,5", NumberStyle::DecimalComma), Some(1234.5));
+        assert_eq!(parse_number("1.234,5", NumberStyle::DecimalPoint), None);
+        assert_eq!(parse_number("1,234.5", NumberStyle::DecimalComma), None);
+        // A lone separator is a decimal in one style and thousands in the other.
+        assert_eq!(parse_number("1.234", NumberStyle::DecimalPoint), Some(1.234));
+        assert_eq!(parse_number("1.234", NumberStyle::DecimalComma), Some(1234.0));
+        assert_eq!(parse_number("1 234 567,8", NumberStyle::DecimalComma), Some(1234567.8));
+    }
+
+    #[test]
+    fn currency_percent_and_accounting_negatives() {
+        assert_eq!(parse_number("$1,200.50", NumberStyle::DecimalPoint), Some(1200.5));
+        assert_eq!(parse_number("(1,000)", NumberStyle::DecimalPoint), Some(-1000.0));
+        assert_eq!(parse_number("-€5", NumberStyle::DecimalPoint), Some(-5.0));
+        assert_eq!(parse_number("12,5%", NumberStyle::DecimalComma), Some(0.125));
+        assert_eq!(parse_number("12 apples", NumberStyle::DecimalPoint), None);
+        assert_eq!(parse_number("", NumberStyle::DecimalPoint), None);
+    }
+
+    #[test]
+    fn a_column_is_read_in_the_style_most_of_it_parses_in() {
+        let ambiguous = DataValue::String("1.000".to_string());
+        let comma = ColumnParsers::detect(&texts(&["1.234,5", "2,75", "1.000"]));
+        assert_eq!(comma.number_style, NumberStyle::DecimalComma);
+        assert_eq!(comma.convert(&ambiguous, &DataType::Number), Some(DataValue::Number(1000.0)));
+
+        let point = ColumnParsers::detect(&texts(&["1,234.5", "2.75", "1.000"]));
+        assert_eq!(point.number_style, NumberStyle::DecimalPoint);
+        assert_eq!(point.convert(&ambiguous, &DataType::Number), Some(DataValue::Number(1.0)));
+    }
+
+    #[test]
+    fn unparsable_cells_up_to_the_threshold_become_empty() {
+        let options = InferenceOptions { error_threshold: 0.1 };
+        // 1 of 10 cells fails: exactly at the threshold.
+        let mut values = texts(&["1", "2", "3", "4", "5", "6", "7", "8", "9", "n/a"]);
+        let worksheet = typed(&sheet(vec![("Amount", values.clone())]), &options, &[]);
+        let column = &worksheet.columns[0];
+        assert_eq!(column.data_type, DataType::Number);
+        assert_eq!(column.value(9), DataValue::Empty);
+        assert_eq!(worksheet.diagnostics.coerced_values.get("Amount"), Some(&1));
+
+        // 2 of 10 is over it, so the column stays text.
+        values[8] = DataValue::String("unknown".to_string());
+        let worksheet = typed(&sheet(vec![("Amount", values)]), &options, &[]);
+        assert_eq!(worksheet.columns[0].data_type, DataType::String);
+        assert!(worksheet.diagnostics.coerced_values.is_empty());
+    }
+
+    #[test]
+    fn empty_cells_do_not_count_towards_the_threshold() {
+        let options = InferenceOptions { error_threshold: 0.0 };
+        let values = vec![DataValue::Empty, DataValue::String("1,5".to_string()), DataValue::Empty];
+        let worksheet = typed(&sheet(vec![("Share", values)]), &options, &[]);
+        assert_eq!(worksheet.columns[0].data_type, DataType::Number);
+        assert_eq!(worksheet.columns[0].value(1), DataValue::Number(1.5));
+    }
+
+    #[test]
+    fn overrides_replace_inference_for_their_sheet_only() {
+        let values = texts(&["1", "2", "three"]);
+        let columns = vec![("Code", values.clone()), ("Other", values)];
+        let overrides = [
+            TypeOverride {
+                sheet: "Sheet".to_string(),
+                column: "Code".to_string(),
+                data_type: DataType::Number,
+            },
+            TypeOverride {
+                sheet: "Elsewhere".to_string(),
+                column: "Other".to_string(),
+                data_type: DataType::Number,
+            },
+        ];
+        let worksheet = typed(&sheet(columns), &InferenceOptions::default(), &overrides);
+        assert_eq!(worksheet.columns[0].data_type, DataType::Number);
+        assert_eq!(worksheet.columns[0].value(2), DataValue::Empty);
+        assert_eq!(worksheet.diagnostics.coerced_values.get("Code"), Some(&1));
+        assert_eq!(worksheet.columns[1].data_type, DataType::String);
+    }
+
+    #[test]
+    fn date_overrides_read_numbers_as_excel_serials() {
+        let serials = vec![
+            DataValue::Number(45_292.0),
+            DataValue::Number(45_292.75),
+            DataValue::Number(59.0),
+            DataValue::Number(-3.0),
+        ];
+        let override_as = |data_type| TypeOverride {
+            sheet: "Sheet".to_string(),
+            column: "Ordered".to_string(),
+            data_type,
+        };
+        let day = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).expect("valid date");
+        let options = InferenceOptions::default();
+
+        let sheet = sheet(vec![("Ordered", serials)]);
+        let worksheet = typed(&sheet, &options, &[override_as(DataType::Date)]);
+        let column = &worksheet.columns[0];
+        assert_eq!(column.value(0), DataValue::Date(day(2024, 1, 1)));
+        assert_eq!(column.value(1), DataValue::Date(day(2024, 1, 1)));
+        assert_eq!(column.value(2), DataValue::Date(day(1900, 2, 28)));
+        assert_eq!(column.value(3), DataValue::Empty);
+        assert_eq!(worksheet.diagnostics.coerced_values.get("Ordered"), Some(&1));
+
+        let worksheet = typed(&sheet, &options, &[override_as(DataType::DateTime)]);
+        let evening = day(2024, 1, 1).and_hms_opt(18, 0, 0).expect("valid time");
+        assert_eq!(worksheet.columns[0].value(1), DataValue::DateTime(evening));
+
+        // Without an override the column stays numeric.
+        assert_eq!(typed(&sheet, &options, &[]).columns[0].data_type, DataType::Number);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::core::data::{DataType, DataValue};
+
+/// One bit per row.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Bitmap {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitmap {
+    fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if bit {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    /// Whether bit `index` is set; bits past the end are unset.
+    fn get(&self, index: usize) -> bool {
+        index < self.len && self.words[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// A column's values in the buffer matching their kind. Rows without a
+/// value hold a placeholder; `Column` tracks which rows are valid.
+#[derive(Debug, Clone)]
+enum ColumnData {
+    /// No row holds a value.
+    Empty,
+    Number(Vec<f64>),
+    /// Elapsed seconds.
+    Duration(Vec<f64>),
+    Boolean(Vec<bool>),
+    Date(Vec<NaiveDate>),
+    DateTime(Vec<NaiveDateTime>),
+    Time(Vec<NaiveTime>),
+    /// Each row holds an index into `dictionary`, which lists every
+    /// distinct text once, in order of first appearance.
+    Text { codes: Vec<u32>, dictionary: Vec<String> },
+    /// Values of more than one kind, kept as they are.
+    Mixed(Vec<DataValue>),
+}
+
+/// A named, typed column of cells.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ColumnRepr", into = "ColumnRepr")]
+pub struct Column {
+    pub name: String,
+    pub data_type: DataType,
+    data: ColumnData,
+    /// Set for rows holding a value; the rest are empty or cell errors.
+    validity: Bitmap,
+    /// Cell errors such as `#N/A`, by row.
+    errors: BTreeMap<usize, String>,
+}
+
+/// The serialized form: one `DataValue` per row.
+#[derive(Clone, Serialize, Deserialize)]
+struct ColumnRepr {
+    name: String,
+    data_type: DataType,
+    values: Vec<DataValue>,
+}
+
+impl From<ColumnRepr> for Column {
+    fn from(repr: ColumnRepr) -> Self {
+        Column::new(repr.name, repr.data_type, repr.values)
+    }
+}
+
+impl From<Column> for ColumnRepr {
+    fn from(column: Column) -> Self {
+        ColumnRepr {
+            values: column.iter().collect(),
+            name: column.name,
+            data_type: column.data_type,
+        }
+    }
+}
+
+impl Column {
+    /// Encodes `values` into the buffer for their kind: a typed array when
+    /// every non-null value has the same kind, `Mixed` otherwise.
+    pub fn new(name: String, data_type: DataType, values: Vec<DataValue>) -> Self {
+        let mut validity = Bitmap::default();
+        let mut errors = BTreeMap::new();
+        for (row, value) in values.iter().enumerate() {
+            validity.push(!value.is_null());
+            if let DataValue::Error(error) = value {
+                errors.insert(row, error.clone());
+            }
+        }
+        let mut kinds = values
+            .iter()
+            .filter(|value| !value.is_null())
+            .map(std::mem::discriminant);
+        let data = match kinds.next() {
+            None => ColumnData::Empty,
+            Some(first) if kinds.all(|kind| kind == first) => encode(values),
+            Some(_) => ColumnData::Mixed(
+                values
+                    .into_iter()
+                    .map(|value| if value.is_null() { DataValue::Empty } else { value })
+                    .collect(),
+            ),
+        };
+        Self {
+            name,
+            data_type,
+            data,
+            validity,
+            errors,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.validity.len()
+    }
+
+    /// The cell at `row`; rows past the end are empty.
+    pub fn value(&self, row: usize) -> DataValue {
+        if !self.validity.get(row) {
+            return match self.errors.get(&row) {
+                Some(error) => DataValue::Error(error.clone()),
+                None => DataValue::Empty,
+            };
+        }
+        match &self.data {
+            ColumnData::Empty => DataValue::Empty,
+            ColumnData::Number(values) => DataValue::Number(values[row]),
+            ColumnData::Duration(values) => DataValue::Duration(values[row]),
+            ColumnData::Boolean(values) => DataValue::Boolean(values[row]),
+            ColumnData::Date(values) => DataValue::Date(values[row]),
+            ColumnData::DateTime(values) => DataValue::DateTime(values[row]),
+            ColumnData::Time(values) => DataValue::Time(values[row]),
+            ColumnData::Text { codes, dictionary } => {
+                DataValue::String(dictionary[codes[row] as usize].clone())
+            }
+            ColumnData::Mixed(values) => values[row].clone(),
+        }
+    }
+
+    /// The number (or a duration's seconds) at `row`, without building a
+    /// `DataValue` for typed number and duration columns.
+    pub fn number(&self, row: usize) -> Option<f64> {
+        match &self.data {
+            ColumnData::Number(values) | ColumnData::Duration(values) if self.validity.get(row) => {
+                Some(values[row])
+            }
+            ColumnData::Mixed(values) => values.get(row).and_then(DataValue::as_f64),
+            _ => None,
+        }
+    }
+
+    /// The dictionary code at `row` of a text column, or `None` for other
+    /// columns and rows without a value.
+    pub fn text_code(&self, row: usize) -> Option<u32> {
+        match &self.data {
+            ColumnData::Text { codes, .. } if self.validity.get(row) => Some(codes[row]),
+            _ => None,
+        }
+    }
+
+    /// The distinct texts of a text column, indexed by code.
+    pub fn dictionary(&self) -> Option<&[String]> {
+        match &self.data {
+            ColumnData::Text { dictionary, .. } => Some(dictionary),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = DataValue> + '_ {
+        (0..self.len()).map(|row| self.value(row))
+    }
+}
+
+/// Moves values that all share one kind into its typed buffer, leaving
+/// placeholders in rows without a value.
+fn encode(values: Vec<DataValue>) -> ColumnData {
+    fn typed<T: Copy>(values: &[DataValue], placeholder: T, get: impl Fn(&DataValue) -> Option<T>) -> Vec<T> {
+        values.iter().map(|value| get(value).unwrap_or(placeholder)).collect()
+    }
+
+    match values.iter().find(|value| !value.is_null()) {
+        Some(DataValue::Number(_)) => ColumnData::Number(typed(&values, 0.0, |value| match value {
+            DataValue::Number(number) => Some(*number),
+            _ => None,
+        })),
+        Some(DataValue::Duration(_)) => ColumnData::Duration(typed(&values, 0.0, |value| match value {
+            DataValue::Duration(seconds) => Some(*seconds),
+            _ => None,
+        })),
+        Some(DataValue::Boolean(_)) => ColumnData::Boolean(typed(&values, false, |value| match value {
+            DataValue::Boolean(flag) => Some(*flag),
+            _ => None,
+        })),
+        Some(DataValue::Date(_)) => ColumnData::Date(typed(&values, NaiveDate::MIN, |value| match value {
+            DataValue::Date(date) => Some(*date),
+            _ => None,
+        })),
+        Some(DataValue::DateTime(_)) => {
+            ColumnData::DateTime(typed(&values, NaiveDateTime::MIN, |value| match value {
+                DataValue::DateTime(datetime) => Some(*datetime),
+                _ => None,
+            }))
+        }
+        Some(DataValue::Time(_)) => ColumnData::Time(typed(&values, NaiveTime::MIN, |value| match value {
+            DataValue::Time(time) => Some(*time),
+            _ => None,
+        })),
+        Some(DataValue::String(_)) => {
+            let mut lookup: HashMap<String, u32> = HashMap::new();
+            let mut dictionary = Vec::new();
+            let codes = values
+                .into_iter()
+                .map(|value| match value {
+                    DataValue::String(text) => match lookup.get(&text) {
+                        Some(code) => *code,
+                        None => {
+                            let code = dictionary.len() as u32;
+                            dictionary.push(text.clone());
+                            lookup.insert(text, code);
+                            code
+                        }
+                    },
+                    _ => 0,
+                })
+                .collect();
+            ColumnData::Text { codes, dictionary }
+        }
+        _ => ColumnData::Empty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> DataValue {
+        DataValue::String(value.to_string())
+    }
+
+    #[test]
+    fn text_is_dictionary_encoded_in_order_of_appearance() {
+        let values = vec![text("b"), text("a"), DataValue::Empty, text("b")];
+        let column = Column::new("Letter".to_string(), DataType::String, values.clone());
+        assert_eq!(column.dictionary(), Some(&["b".to_string(), "a".to_string()][..]));
+        let codes = (0..column.len()).map(|row| column.text_code(row)).collect::<Vec<_>>();
+        assert_eq!(codes, [Some(0), Some(1), None, Some(0)]);
+        assert_eq!(column.iter().collect::<Vec<_>>(), values);
+    }
+
+    #[test]
+    fn mixed_kinds_fall_back_to_plain_values() {
+        let values = vec![DataValue::Number(1.5), text("n/a"), DataValue::Empty];
+        let column = Column::new("Amount".to_string(), DataType::Number, values.clone());
+        assert!(matches!(column.data, ColumnData::Mixed(_)));
+        assert_eq!(column.dictionary(), None);
+        assert_eq!(column.number(0), Some(1.5));
+        assert_eq!(column.number(1), None);
+        assert_eq!(column.iter().collect::<Vec<_>>(), values);
+    }
+
+    #[test]
+    fn validity_holds_across_word_boundaries() {
+        let values = (0..130)
+            .map(|row| match row {
+                63 | 64 | 129 => DataValue::Empty,
+                _ => DataValue::Number(row as f64),
+            })
+            .collect::<Vec<_>>();
+        let column = Column::new("Amount".to_string(), DataType::Number, values);
+        assert!(matches!(column.data, ColumnData::Number(_)));
+        assert_eq!(column.len(), 130);
+        assert_eq!(column.number(62), Some(62.0));
+        assert_eq!(column.number(63), None);
+        assert_eq!(column.number(64), None);
+        assert_eq!(column.number(65), Some(65.0));
+        assert_eq!(column.value(129), DataValue::Empty);
+        assert_eq!(column.value(130), DataValue::Empty);
+    }
+
+    #[test]
+    fn cell_errors_survive_a_serde_round_trip() {
+        let values = vec![
+            DataValue::Number(1.0),
+            DataValue::Error("#DIV/0!".to_string()),
+            DataValue::Empty,
+        ];
+        let column = Column::new("Ratio".to_string(), DataType::Number, values.clone());
+        assert_eq!(column.number(1), None);
+        let json = serde_json::to_string(&column).expect("serializable");
+        let restored = serde_json::from_str::<Column>(&json).expect("deserializable");
+        assert_eq!(restored.name, "Ratio");
+        assert_eq!(restored.data_type, DataType::Number);
+        assert_eq!(restored.iter().collect::<Vec<_>>(), values);
+        assert_eq!(restored.validity, column.validity);
+    }
+}
@@ -1,4 +1,6 @@
-use chrono::NaiveDate;
+use std::cmp::Ordering;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -6,16 +8,34 @@ pub enum DataType {
     String,
     Number,
     Date,
+    DateTime,
+    Time,
+    Duration,
     Boolean,
     Empty,
 }
 
+impl DataType {
+    /// Whether values carry a calendar date, with or without a time of day.
+    pub fn is_temporal(&self) -> bool {
+        matches!(self, DataType::Date | DataType::DateTime)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum DataValue {
     String(String),
     Number(f64),
     Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    /// A time of day without a date.
+    Time(NaiveTime),
+    /// Elapsed time in seconds, such as an Excel `[h]:mm` cell.
+    Duration(f64),
     Boolean(bool),
+    /// A spreadsheet error such as `#DIV/0!` or `#N/A`. It displays as
+    /// written but counts as a missing value everywhere else.
+    Error(String),
     Empty,
 }
 
@@ -25,8 +45,11 @@ impl DataValue {
             DataValue::String(_) => DataType::String,
             DataValue::Number(_) => DataType::Number,
             DataValue::Date(_) => DataType::Date,
+            DataValue::DateTime(_) => DataType::DateTime,
+            DataValue::Time(_) => DataType::Time,
+            DataValue::Duration(_) => DataType::Duration,
             DataValue::Boolean(_) => DataType::Boolean,
-            DataValue::Empty => DataType::Empty,
+            DataValue::Error(_) | DataValue::Empty => DataType::Empty,
         }
     }
 
@@ -35,28 +58,129 @@ impl DataValue {
             DataValue::String(value) => value.clone(),
             DataValue::Number(value) => format!("{value}"),
             DataValue::Date(value) => value.format("%Y-%m-%d").to_string(),
+            DataValue::DateTime(value) => value.format("%Y-%m-%d %H:%M:%S").to_string(),
+            DataValue::Time(value) => value.format("%H:%M:%S").to_string(),
+            DataValue::Duration(seconds) => format_duration(*seconds),
             DataValue::Boolean(value) => value.to_string(),
+            DataValue::Error(error) => error.clone(),
             DataValue::Empty => "".to_string(),
         }
     }
 
+    /// Whether the value is missing: an empty cell or a cell error.
+    pub fn is_null(&self) -> bool {
+        matches!(self, DataValue::Empty | DataValue::Error(_))
+    }
+
+    /// The number, or a duration's seconds.
     pub fn as_f64(&self) -> Option<f64> {
         match self {
-            DataValue::Number(value) => Some(*value),
+            DataValue::Number(value) | DataValue::Duration(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The calendar date of a `Date` or `DateTime`.
+    pub fn as_date(&self) -> Option<NaiveDate> {
+        match self {
+            DataValue::Date(value) => Some(*value),
+            DataValue::DateTime(value) => Some(value.date()),
             _ => None,
         }
     }
+
+    /// A `Date` or `DateTime` as a timestamp; dates start at midnight.
+    pub fn as_datetime(&self) -> Option<NaiveDateTime> {
+        match self {
+            DataValue::Date(value) => Some(value.and_time(NaiveTime::MIN)),
+            DataValue::DateTime(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Orders values by type-aware rules: numbers numerically, dates and
+    /// times chronologically (a date sorts as its midnight), text naturally (`Item 2` before `Item 10`),
+    /// then cell errors, and empty cells after everything else.
+    pub fn compare(&self, other: &DataValue) -> Ordering {
+        match (self, other) {
+            (DataValue::Empty, DataValue::Empty) => Ordering::Equal,
+            (DataValue::Empty, _) => Ordering::Greater,
+            (_, DataValue::Empty) => Ordering::Less,
+            (DataValue::Number(left), DataValue::Number(right)) => left.total_cmp(right),
+            (DataValue::Time(left), DataValue::Time(right)) => left.cmp(right),
+            (DataValue::Duration(left), DataValue::Duration(right)) => left.total_cmp(right),
+            (left, right) if left.as_datetime().is_some() && right.as_datetime().is_some() => {
+                left.as_datetime().cmp(&right.as_datetime())
+            }
+            (DataValue::Boolean(left), DataValue::Boolean(right)) => left.cmp(right),
+            (DataValue::String(left), DataValue::String(right)) => natural_cmp(left, right),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            DataValue::Number(_) => 0,
+            DataValue::Date(_) | DataValue::DateTime(_) => 1,
+            DataValue::Time(_) => 2,
+            DataValue::Duration(_) => 3,
+            DataValue::Boolean(_) => 4,
+            DataValue::String(_) => 5,
+            DataValue::Error(_) => 6,
+            DataValue::Empty => 7,
+        }
+    }
+}
+
+/// Formats seconds as `[-]h:mm:ss`, letting hours exceed a day.
+fn format_duration(seconds: f64) -> String {
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let total = seconds.abs().round() as u64;
+    format!(
+        "{sign}{}:{:02}:{:02}",
+        total / 3600,
+        total / 60 % 60,
+        total % 60
+    )
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Column {
-    pub name: String,
-    pub data_type: DataType,
-    pub values: Vec<DataValue>,
+/// Compares text case-insensitively while treating embedded digit runs as numbers.
+pub fn natural_cmp(left: &str, right: &str) -> Ordering {
+    let mut left_chars = left.chars().peekable();
+    let mut right_chars = right.chars().peekable();
+    loop {
+        match (left_chars.peek(), right_chars.peek()) {
+            (None, None) => return left.cmp(right),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let left_digits = take_digits(&mut left_chars);
+                let right_digits = take_digits(&mut right_chars);
+                let ordering = left_digits
+                    .len()
+                    .cmp(&right_digits.len())
+                    .then_with(|| left_digits.cmp(&right_digits));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(l), Some(r)) => {
+                let ordering = l.to_lowercase().cmp(r.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                left_chars.next();
+                right_chars.next();
+            }
+        }
+    }
 }
 
-impl Column {
-    pub fn len(&self) -> usize {
-        self.values.len()
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(ch) = chars.next_if(|ch| ch.is_ascii_digit()) {
+        digits.push(ch);
     }
+    let trimmed = digits.trim_start_matches('0');
+    trimmed.to_string()
 }
@@ -0,0 +1,217 @@
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// Calendar components used by date functions and date binning.
+///
+/// `Week` counts Sunday-started weeks; `IsoWeek` follows ISO 8601 with
+/// Monday-started weeks. `Weekday` numbers days from Monday (1) to Sunday (7).
+/// `Hour` and `Minute` only vary for timestamps; plain dates sit at midnight.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum DatePart {
+    Year,
+    Quarter,
+    Month,
+    Week,
+    IsoWeek,
+    Day,
+    Weekday,
+    DayOfYear,
+    Hour,
+    Minute,
+}
+
+impl DatePart {
+    pub const ALL: [DatePart; 10] = [
+        DatePart::Year,
+        DatePart::Quarter,
+        DatePart::Month,
+        DatePart::Week,
+        DatePart::IsoWeek,
+        DatePart::Day,
+        DatePart::Weekday,
+        DatePart::DayOfYear,
+        DatePart::Hour,
+        DatePart::Minute,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DatePart::Year => "Year",
+            DatePart::Quarter => "Quarter",
+            DatePart::Month => "Month",
+            DatePart::Week => "Week",
+            DatePart::IsoWeek => "ISO week",
+            DatePart::Day => "Day",
+            DatePart::Weekday => "Weekday",
+            DatePart::DayOfYear => "Day of year",
+            DatePart::Hour => "Hour",
+            DatePart::Minute => "Minute",
+        }
+    }
+
+    pub fn is_time_of_day(self) -> bool {
+        matches!(self, DatePart::Hour | DatePart::Minute)
+    }
+
+    /// Whether the part names a span of days that dates can be truncated to.
+    pub fn is_period(self) -> bool {
+        !matches!(self, DatePart::Weekday | DatePart::DayOfYear)
+    }
+
+    /// The next level of the Year → Quarter → Month → Day → Hour → Minute
+    /// hierarchy.
+    pub fn drill_down(self) -> Option<DatePart> {
+        match self {
+            DatePart::Year => Some(DatePart::Quarter),
+            DatePart::Quarter => Some(DatePart::Month),
+            DatePart::Month => Some(DatePart::Day),
+            DatePart::Day => Some(DatePart::Hour),
+            DatePart::Hour => Some(DatePart::Minute),
+            _ => None,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "year" => Some(DatePart::Year),
+            "quarter" => Some(DatePart::Quarter),
+            "month" => Some(DatePart::Month),
+            "week" => Some(DatePart::Week),
+            "iso-week" | "isoweek" => Some(DatePart::IsoWeek),
+            "day" => Some(DatePart::Day),
+            "weekday" => Some(DatePart::Weekday),
+            "dayofyear" => Some(DatePart::DayOfYear),
+            "hour" => Some(DatePart::Hour),
+            "minute" => Some(DatePart::Minute),
+            _ => None,
+        }
+    }
+
+    /// The numeric value of this part of `date`, e.g. 3 for the quarter of
+    /// 2024-08-01.
+    pub fn extract(self, date: NaiveDate) -> i64 {
+        let value = match self {
+            DatePart::Year => date.year(),
+            DatePart::Quarter => (date.month0() / 3 + 1) as i32,
+            DatePart::Month => date.month() as i32,
+            DatePart::Week => {
+                let jan_first = NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap_or(date);
+                let offset = jan_first.weekday().num_days_from_sunday();
+                ((date.ordinal0() + offset) / 7 + 1) as i32
+            }
+            DatePart::IsoWeek => date.iso_week().week() as i32,
+            DatePart::Day => date.day() as i32,
+            DatePart::Weekday => date.weekday().number_from_monday() as i32,
+            DatePart::DayOfYear => date.ordinal() as i32,
+            DatePart::Hour | DatePart::Minute => 0,
+        };
+        i64::from(value)
+    }
+
+    pub fn extract_datetime(self, datetime: NaiveDateTime) -> i64 {
+        match self {
+            DatePart::Hour => i64::from(datetime.hour()),
+            DatePart::Minute => i64::from(datetime.minute()),
+            _ => self.extract(datetime.date()),
+        }
+    }
+
+    /// The first day of the period containing `date`. Parts that are not
+    /// periods (`Weekday`, `DayOfYear`) truncate to the day itself.
+    pub fn truncate(self, date: NaiveDate) -> NaiveDate {
+        let truncated = match self {
+            DatePart::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1),
+            DatePart::Quarter => NaiveDate::from_ymd_opt(date.year(), date.month0() / 3 * 3 + 1, 1),
+            DatePart::Month => NaiveDate::from_ymd_opt(date.year(), date.month(), 1),
+            DatePart::Week => date.checked_sub_days(Days::new(u64::from(
+                date.weekday().num_days_from_sunday(),
+            ))),
+            DatePart::IsoWeek => date.checked_sub_days(Days::new(u64::from(
+                date.weekday().num_days_from_monday(),
+            ))),
+            DatePart::Day
+            | DatePart::Weekday
+            | DatePart::DayOfYear
+            | DatePart::Hour
+            | DatePart::Minute => Some(date),
+        };
+        truncated.unwrap_or(date)
+    }
+
+    pub fn truncate_datetime(self, datetime: NaiveDateTime) -> NaiveDateTime {
+        let time = match self {
+            DatePart::Hour => NaiveTime::from_hms_opt(datetime.hour(), 0, 0),
+            DatePart::Minute => NaiveTime::from_hms_opt(datetime.hour(), datetime.minute(), 0),
+            _ => return self.truncate(datetime.date()).and_time(NaiveTime::MIN),
+        };
+        datetime.date().and_time(time.unwrap_or(NaiveTime::MIN))
+    }
+
+    /// Moves `date` by `amount` units of this part.
+    pub fn add(self, date: NaiveDate, amount: i64) -> Option<NaiveDate> {
+        let shift_months = |months: i64| {
+            let magnitude = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+            if months >= 0 {
+                date.checked_add_months(magnitude)
+            } else {
+                date.checked_sub_months(magnitude)
+            }
+        };
+        let shift_days = |days: i64| {
+            let magnitude = Days::new(days.unsigned_abs());
+            if days >= 0 {
+                date.checked_add_days(magnitude)
+            } else {
+                date.checked_sub_days(magnitude)
+            }
+        };
+        match self {
+            DatePart::Year => shift_months(amount.checked_mul(12)?),
+            DatePart::Quarter => shift_months(amount.checked_mul(3)?),
+            DatePart::Month => shift_months(amount),
+            DatePart::Week | DatePart::IsoWeek => shift_days(amount.checked_mul(7)?),
+            DatePart::Day | DatePart::Weekday | DatePart::DayOfYear => shift_days(amount),
+            DatePart::Hour | DatePart::Minute => self
+                .add_datetime(date.and_time(NaiveTime::MIN), amount)
+                .map(|datetime| datetime.date()),
+        }
+    }
+
+    /// Moves `datetime` by `amount` units of this part, keeping the time of
+    /// day for calendar parts.
+    pub fn add_datetime(self, datetime: NaiveDateTime, amount: i64) -> Option<NaiveDateTime> {
+        match self {
+            DatePart::Hour => datetime.checked_add_signed(TimeDelta::try_hours(amount)?),
+            DatePart::Minute => datetime.checked_add_signed(TimeDelta::try_minutes(amount)?),
+            _ => Some(self.add(datetime.date(), amount)?.and_time(datetime.time())),
+        }
+    }
+
+    /// Number of period boundaries crossed between `start` and `end`.
+    pub fn diff(self, start: NaiveDate, end: NaiveDate) -> i64 {
+        let months = |date: NaiveDate| i64::from(date.year()) * 12 + i64::from(date.month0());
+        match self {
+            DatePart::Year => i64::from(end.year() - start.year()),
+            DatePart::Quarter => months(end) / 3 - months(start) / 3,
+            DatePart::Month => months(end) - months(start),
+            DatePart::Week | DatePart::IsoWeek => {
+                (self.truncate(end) - self.truncate(start)).num_days() / 7
+            }
+            DatePart::Day | DatePart::Weekday | DatePart::DayOfYear => (end - start).num_days(),
+            DatePart::Hour => (end - start).num_hours(),
+            DatePart::Minute => (end - start).num_minutes(),
+        }
+    }
+
+    pub fn diff_datetime(self, start: NaiveDateTime, end: NaiveDateTime) -> i64 {
+        match self {
+            DatePart::Hour => {
+                (self.truncate_datetime(end) - self.truncate_datetime(start)).num_hours()
+            }
+            DatePart::Minute => {
+                (self.truncate_datetime(end) - self.truncate_datetime(start)).num_minutes()
+            }
+            _ => self.diff(start.date(), end.date()),
+        }
+    }
+}
@@ -1,2 +1,4 @@
+pub mod column;
 pub mod data;
+pub mod dates;
 pub mod workbook;
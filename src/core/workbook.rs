@@ -1,21 +1,103 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 
-use crate::core::data::Column;
+use crate::core::column::Column;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Worksheet {
     pub name: String,
     pub columns: Vec<Column>,
     pub row_count: usize,
+    #[serde(default)]
+    pub diagnostics: SheetDiagnostics,
 }
 
-impl Worksheet {
-    pub fn column_names(&self) -> Vec<String> {
-        self.columns.iter().map(|col| col.name.clone()).collect()
+/// A data source listed in a workbook, available before its cells are read.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SheetInfo {
+    pub name: String,
+    pub source: SheetSource,
+}
+
+/// Where a worksheet's cells come from inside the workbook.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum SheetSource {
+    #[default]
+    Sheet,
+    /// An Excel table (ListObject) on `sheet`.
+    Table { sheet: String, table: String },
+    /// A defined name referring to a cell range.
+    DefinedName { name: String, reference: String },
+}
+
+impl SheetInfo {
+    /// The name shown in the sheet selector.
+    pub fn display_name(&self) -> String {
+        match &self.source {
+            SheetSource::Sheet => self.name.clone(),
+            SheetSource::Table { sheet, .. } => format!("{} (table on {sheet})", self.name),
+            SheetSource::DefinedName { reference, .. } => format!("{} ({reference})", self.name),
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// What loading and typing changed or could not read in one sheet.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SheetDiagnostics {
+    /// Cell errors such as `#DIV/0!`, counted by error text.
+    pub error_cells: BTreeMap<String, usize>,
+    /// Rows shorter than the sheet, padded with empty cells.
+    pub padded_rows: usize,
+    /// Unnamed columns to the right of the header that were kept.
+    pub extra_columns: usize,
+    /// Unnamed columns to the right of the header that were dropped.
+    pub dropped_columns: usize,
+    /// Cells per column that did not fit the column's type and were emptied.
+    pub coerced_values: BTreeMap<String, usize>,
+}
+
+impl SheetDiagnostics {
+    /// One line per finding, for display.
+    pub fn messages(&self) -> Vec<String> {
+        let mut messages = self
+            .error_cells
+            .iter()
+            .map(|(error, count)| format!("{count} {error} cell(s), treated as empty"))
+            .collect::<Vec<_>>();
+        if self.padded_rows > 0 {
+            messages.push(format!("Padded {} short row(s) with empty cells", self.padded_rows));
+        }
+        if self.extra_columns > 0 {
+            messages.push(format!("Kept {} column(s) beyond the header", self.extra_columns));
+        }
+        if self.dropped_columns > 0 {
+            messages.push(format!("Dropped {} column(s) beyond the header", self.dropped_columns));
+        }
+        messages.extend(self.coerced_values.iter().map(|(column, count)| {
+            format!("{column}: emptied {count} value(s) that did not fit the column type")
+        }));
+        messages
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Workbook {
-    pub sheets: Vec<Worksheet>,
+    /// Every data source in the file, in selector order.
+    pub sources: Vec<SheetInfo>,
+    /// The sources parsed so far, shared with background queries.
+    pub sheets: Vec<Arc<Worksheet>>,
+    /// Sources that could not be read, with the reason.
+    pub skipped_sheets: Vec<(String, String)>,
+}
+
+impl Workbook {
+    pub fn sheet(&self, name: &str) -> Option<&Arc<Worksheet>> {
+        self.sheets.iter().find(|sheet| sheet.name == name)
+    }
+
+    pub fn is_skipped(&self, name: &str) -> bool {
+        self.skipped_sheets.iter().any(|(skipped, _)| skipped == name)
+    }
 }
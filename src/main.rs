@@ -1,15 +1,23 @@
+mod bench;
+mod calc;
 mod connectors;
 mod core;
 mod query;
 mod storage;
 mod ui;
 mod viz;
+mod worker;
 
 fn main() -> eframe::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|arg| arg == "--bench") {
+        bench::run(args.get(1).and_then(|rows| rows.parse().ok()));
+        return Ok(());
+    }
     let native_options = eframe::NativeOptions::default();
     eframe::run_native(
         "Vizualizer",
         native_options,
-        Box::new(|_cc| Ok(Box::new(ui::VizualizerApp::new()))),
+        Box::new(|cc| Ok(Box::new(ui::VizualizerApp::new(cc.egui_ctx.clone())))),
     )
 }
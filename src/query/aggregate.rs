@@ -0,0 +1,520 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::core::data::DataValue;
+use crate::query::model::Aggregation;
+use crate::query::sketch::{DistinctSketch, QuantileSketch};
+
+/// Values a distinct count or percentile keeps exactly before switching to
+/// a sketch, bounding the memory of each group.
+const EXACT_LIMIT: usize = 4096;
+
+/// The running state of one aggregation over a group's non-empty values,
+/// fed in row order. States of the same aggregation merge, so groups can be
+/// aggregated in parts and combined.
+#[derive(Debug)]
+pub struct Accumulator {
+    aggregation: Aggregation,
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    Count(usize),
+    Sum(f64),
+    Mean { sum: f64, count: usize },
+    Min(Option<f64>),
+    Max(Option<f64>),
+    /// Count, mean and sum of squared deviations from the mean.
+    Moments { count: usize, mean: f64, squares: f64 },
+    Distinct(Distinct),
+    Quantile(Quantiles),
+    /// The value with its row, so merges keep the earliest (or latest)
+    /// row whichever order parts arrive in.
+    First(Option<(usize, DataValue)>),
+    Last(Option<(usize, DataValue)>),
+    /// Occurrences of each display string, with the first value shown so.
+    Mode(HashMap<String, (usize, DataValue)>),
+}
+
+/// Values are tracked by a 64-bit hash of their display string, so the
+/// exact count only risks a hash collision.
+#[derive(Debug)]
+enum Distinct {
+    Exact(HashSet<u64>),
+    Sketch(DistinctSketch),
+}
+
+#[derive(Debug)]
+enum Quantiles {
+    Exact(Vec<f64>),
+    Sketch(QuantileSketch),
+}
+
+impl Accumulator {
+    pub fn new(aggregation: Aggregation) -> Self {
+        let state = match aggregation {
+            Aggregation::Count => State::Count(0),
+            Aggregation::Sum => State::Sum(0.0),
+            Aggregation::Avg => State::Mean { sum: 0.0, count: 0 },
+            Aggregation::Min => State::Min(None),
+            Aggregation::Max => State::Max(None),
+            Aggregation::StdDev
+            | Aggregation::StdDevPop
+            | Aggregation::Variance
+            | Aggregation::VariancePop => State::Moments {
+                count: 0,
+                mean: 0.0,
+                squares: 0.0,
+            },
+            Aggregation::CountDistinct => State::Distinct(Distinct::Exact(HashSet::new())),
+            Aggregation::Median | Aggregation::Percentile(_) => State::Quantile(Quantiles::Exact(Vec::new())),
+            Aggregation::First => State::First(None),
+            Aggregation::Last => State::Last(None),
+            Aggregation::Mode => State::Mode(HashMap::new()),
+        };
+        Self { aggregation, state }
+    }
+
+    /// Adds one non-empty value from sheet row `row`. Numeric aggregations
+    /// skip values that are not numbers.
+    pub fn add(&mut self, row: usize, value: DataValue) {
+        match &mut self.state {
+            State::Count(count) => *count += 1,
+            State::Distinct(distinct) => distinct.insert(hash_display(&value)),
+            State::First(first) => {
+                first.get_or_insert((row, value));
+            }
+            State::Last(last) => *last = Some((row, value)),
+            State::Mode(counts) => counts.entry(value.display_string()).or_insert((0, value)).0 += 1,
+            numeric => {
+                if let Some(number) = value.as_f64() {
+                    numeric.add_number(number);
+                }
+            }
+        }
+    }
+
+    /// Folds in `other`, which holds the values that came after this
+    /// state's own.
+    pub fn merge(&mut self, other: Accumulator) {
+        match (&mut self.state, other.state) {
+            (State::Count(count), State::Count(other)) => *count += other,
+            (State::Sum(sum), State::Sum(other)) => *sum += other,
+            (State::Mean { sum, count }, State::Mean { sum: other_sum, count: other_count }) => {
+                *sum += other_sum;
+                *count += other_count;
+            }
+            (State::Min(min), State::Min(other)) => *min = fold_option(*min, other, f64::min),
+            (State::Max(max), State::Max(other)) => *max = fold_option(*max, other, f64::max),
+            (
+                State::Moments { count, mean, squares },
+                State::Moments { count: other_count, mean: other_mean, squares: other_squares },
+            ) => {
+                if other_count == 0 {
+                    return;
+                }
+                let total = *count + other_count;
+                let delta = other_mean - *mean;
+                *mean += delta * other_count as f64 / total as f64;
+                *squares += other_squares + delta * delta * (*count * other_count) as f64 / total as f64;
+                *count = total;
+            }
+            (State::Distinct(distinct), State::Distinct(other)) => distinct.merge(other),
+            (State::Quantile(quantiles), State::Quantile(other)) => quantiles.merge(other),
+            (State::First(first), State::First(Some(other)))
+                if first.as_ref().is_none_or(|(row, _)| other.0 < *row) =>
+            {
+                *first = Some(other)
+            }
+            (State::Last(last), State::Last(Some(other)))
+                if last.as_ref().is_none_or(|(row, _)| other.0 > *row) =>
+            {
+                *last = Some(other)
+            }
+            (State::Mode(counts), State::Mode(other)) => {
+                for (key, (count, value)) in other {
+                    counts.entry(key).or_insert((0, value)).0 += count;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The aggregate of the values added so far.
+    pub fn finish(&self) -> DataValue {
+        let number = match (&self.state, self.aggregation) {
+            (State::Count(count), _) => Some(*count as f64),
+            (State::Sum(sum), _) => Some(*sum),
+            (State::Mean { sum, count }, _) => (*count > 0).then(|| sum / *count as f64),
+            (State::Min(min), _) => *min,
+            (State::Max(max), _) => *max,
+            (State::Moments { count, squares, .. }, aggregation) => {
+                let ddof = match aggregation {
+                    Aggregation::StdDev | Aggregation::Variance => 1,
+                    _ => 0,
+                };
+                let variance = (*count > ddof).then(|| squares / (*count - ddof) as f64);
+                match aggregation {
+                    Aggregation::StdDev | Aggregation::StdDevPop => variance.map(f64::sqrt),
+                    _ => variance,
+                }
+            }
+            (State::Distinct(distinct), _) => Some(distinct.count()),
+            (State::Quantile(quantiles), aggregation) => {
+                let p = match aggregation {
+                    Aggregation::Percentile(p) => f64::from(p),
+                    _ => 50.0,
+                };
+                quantiles.percentile(p)
+            }
+            (State::First(value) | State::Last(value), _) => {
+                return value.as_ref().map_or(DataValue::Empty, |(_, value)| value.clone());
+            }
+            (State::Mode(counts), _) => return mode(counts),
+        };
+        number.map(DataValue::Number).unwrap_or(DataValue::Empty)
+    }
+}
+
+impl State {
+    fn add_number(&mut self, number: f64) {
+        match self {
+            State::Sum(sum) => *sum += number,
+            State::Mean { sum, count } => {
+                *sum += number;
+                *count += 1;
+            }
+            State::Min(min) => *min = fold_option(*min, Some(number), f64::min),
+            State::Max(max) => *max = fold_option(*max, Some(number), f64::max),
+            // Welford's update keeps the squares accurate in one pass.
+            State::Moments { count, mean, squares } => {
+                *count += 1;
+                let delta = number - *mean;
+                *mean += delta / *count as f64;
+                *squares += delta * (number - *mean);
+            }
+            State::Quantile(quantiles) => quantiles.insert(number),
+            _ => {}
+        }
+    }
+}
+
+impl Distinct {
+    fn insert(&mut self, hash: u64) {
+        match self {
+            Distinct::Exact(hashes) => {
+                hashes.insert(hash);
+                if hashes.len() > EXACT_LIMIT {
+                    let mut sketch = DistinctSketch::new();
+                    hashes.drain().for_each(|hash| sketch.insert_hash(hash));
+                    *self = Distinct::Sketch(sketch);
+                }
+            }
+            Distinct::Sketch(sketch) => sketch.insert_hash(hash),
+        }
+    }
+
+    fn merge(&mut self, other: Distinct) {
+        match other {
+            Distinct::Exact(hashes) => hashes.into_iter().for_each(|hash| self.insert(hash)),
+            Distinct::Sketch(mut other) => {
+                if let Distinct::Exact(hashes) = self {
+                    hashes.drain().for_each(|hash| other.insert_hash(hash));
+                } else if let Distinct::Sketch(sketch) = self {
+                    other.merge(sketch);
+                }
+                *self = Distinct::Sketch(other);
+            }
+        }
+    }
+
+    fn count(&self) -> f64 {
+        match self {
+            Distinct::Exact(hashes) => hashes.len() as f64,
+            Distinct::Sketch(sketch) => sketch.estimate().round(),
+        }
+    }
+}
+
+impl Quantiles {
+    fn insert(&mut self, number: f64) {
+        match self {
+            Quantiles::Exact(numbers) => {
+                numbers.push(number);
+                if numbers.len() > EXACT_LIMIT {
+                    let mut sketch = QuantileSketch::new();
+                    numbers.drain(..).for_each(|number| sketch.insert(number));
+                    *self = Quantiles::Sketch(sketch);
+                }
+            }
+            Quantiles::Sketch(sketch) => sketch.insert(number),
+        }
+    }
+
+    fn merge(&mut self, other: Quantiles) {
+        match other {
+            Quantiles::Exact(numbers) => numbers.into_iter().for_each(|number| self.insert(number)),
+            Quantiles::Sketch(other) => {
+                if let Quantiles::Exact(numbers) = self {
+                    let mut sketch = QuantileSketch::new();
+                    numbers.drain(..).for_each(|number| sketch.insert(number));
+                    *self = Quantiles::Sketch(sketch);
+                }
+                if let Quantiles::Sketch(sketch) = self {
+                    sketch.merge(&other);
+                }
+            }
+        }
+    }
+
+    fn percentile(&self, p: f64) -> Option<f64> {
+        match self {
+            Quantiles::Exact(numbers) => percentile(numbers, p),
+            Quantiles::Sketch(sketch) => sketch.percentile(p),
+        }
+    }
+}
+
+fn fold_option(current: Option<f64>, other: Option<f64>, fold: fn(f64, f64) -> f64) -> Option<f64> {
+    match (current, other) {
+        (Some(current), Some(other)) => Some(fold(current, other)),
+        (current, other) => current.or(other),
+    }
+}
+
+/// Hashes the value's display string, without building it for text.
+fn hash_display(value: &DataValue) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match value {
+        DataValue::String(text) => text.hash(&mut hasher),
+        other => other.display_string().hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+/// Linearly interpolated percentile, matching the default used by most
+/// spreadsheet and statistics tools.
+fn percentile(numbers: &[f64], p: f64) -> Option<f64> {
+    if numbers.is_empty() {
+        return None;
+    }
+    let mut sorted = numbers.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = (p.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let weight = rank - lower as f64;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * weight)
+}
+
+/// Most frequent value; ties go to the value that sorts first.
+fn mode(counts: &HashMap<String, (usize, DataValue)>) -> DataValue {
+    counts
+        .values()
+        .max_by(|(left_count, left), (right_count, right)| {
+            left_count.cmp(right_count).then_with(|| right.compare(left))
+        })
+        .map(|(_, value)| value.clone())
+        .unwrap_or(DataValue::Empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGGREGATIONS: [Aggregation; 15] = [
+        Aggregation::Count,
+        Aggregation::Sum,
+        Aggregation::Avg,
+        Aggregation::Min,
+        Aggregation::Max,
+        Aggregation::StdDev,
+        Aggregation::StdDevPop,
+        Aggregation::Variance,
+        Aggregation::VariancePop,
+        Aggregation::CountDistinct,
+        Aggregation::Median,
+        Aggregation::Percentile(90),
+        Aggregation::First,
+        Aggregation::Last,
+        Aggregation::Mode,
+    ];
+
+    fn accumulate(aggregation: Aggregation, values: &[DataValue]) -> Accumulator {
+        let mut accumulator = Accumulator::new(aggregation);
+        values.iter().enumerate().for_each(|(row, value)| accumulator.add(row, value.clone()));
+        accumulator
+    }
+
+    /// Accumulates each chunk on its own and merges them in order.
+    fn merged(aggregation: Aggregation, values: &[DataValue], chunk: usize) -> Accumulator {
+        let mut parts = values.chunks(chunk).enumerate().map(|(index, part)| {
+            let mut accumulator = Accumulator::new(aggregation);
+            for (offset, value) in part.iter().enumerate() {
+                accumulator.add(index * chunk + offset, value.clone());
+            }
+            accumulator
+        });
+        let mut accumulator = parts.next().unwrap_or_else(|| Accumulator::new(aggregation));
+        parts.for_each(|part| accumulator.merge(part));
+        accumulator
+    }
+
+    fn numbers(values: impl IntoIterator<Item = f64>) -> Vec<DataValue> {
+        values.into_iter().map(DataValue::Number).collect()
+    }
+
+    fn assert_close(actual: DataValue, expected: DataValue, tolerance: f64) {
+        match (actual.as_f64(), expected.as_f64()) {
+            (Some(actual), Some(expected)) => assert!(
+                (actual - expected).abs() <= tolerance * expected.abs().max(1.0),
+                "{actual} differs from {expected}"
+            ),
+            _ => assert_eq!(actual, expected),
+        }
+    }
+
+    #[test]
+    fn merged_parts_match_a_single_pass() {
+        let values = (0..1_000)
+            .map(|index| match index % 7 {
+                0 => DataValue::String(format!("label {}", index % 5)),
+                _ => DataValue::Number(((index * 37) % 101) as f64 / 4.0),
+            })
+            .collect::<Vec<_>>();
+        for aggregation in AGGREGATIONS {
+            let single = accumulate(aggregation, &values).finish();
+            for chunk in [1, 7, 333, 1_000] {
+                assert_close(merged(aggregation, &values, chunk).finish(), single.clone(), 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn merging_an_empty_part_changes_nothing() {
+        let values = numbers([4.0, 8.0, 15.0, 16.0, 23.0, 42.0]);
+        for aggregation in AGGREGATIONS {
+            let mut accumulator = accumulate(aggregation, &values);
+            accumulator.merge(Accumulator::new(aggregation));
+            let mut empty = Accumulator::new(aggregation);
+            empty.merge(accumulate(aggregation, &values));
+            let single = accumulate(aggregation, &values).finish();
+            assert_eq!(accumulator.finish(), single);
+            assert_eq!(empty.finish(), single);
+        }
+    }
+
+    #[test]
+    fn variance_merge_matches_two_pass_on_offset_values() {
+        // A large offset makes the naive sum-of-squares formula lose every
+        // significant digit; Welford's update and Chan's merge do not.
+        let raw = (0..10_000).map(|index| 1e9 + (index % 13) as f64).collect::<Vec<_>>();
+        let mean = raw.iter().sum::<f64>() / raw.len() as f64;
+        let squares = raw.iter().map(|value| (value - mean).powi(2)).sum::<f64>();
+        let values = numbers(raw.iter().copied());
+        let expected = [
+            (Aggregation::Variance, squares / (raw.len() - 1) as f64),
+            (Aggregation::VariancePop, squares / raw.len() as f64),
+            (Aggregation::StdDev, (squares / (raw.len() - 1) as f64).sqrt()),
+        ];
+        for (aggregation, expected) in expected {
+            for chunk in [10_000, 4_999, 17] {
+                let actual = merged(aggregation, &values, chunk).finish();
+                assert_close(actual, DataValue::Number(expected), 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn sample_variance_needs_two_values() {
+        let one = numbers([5.0]);
+        assert_eq!(accumulate(Aggregation::StdDev, &one).finish(), DataValue::Empty);
+        assert_eq!(accumulate(Aggregation::StdDevPop, &one).finish(), DataValue::Number(0.0));
+    }
+
+    #[test]
+    fn distinct_count_is_exact_up_to_the_limit() {
+        let values = (0..EXACT_LIMIT)
+            .map(|index| DataValue::String(format!("value {index}")))
+            .collect::<Vec<_>>();
+        let accumulator = accumulate(Aggregation::CountDistinct, &values);
+        assert!(matches!(accumulator.state, State::Distinct(Distinct::Exact(_))));
+        assert_eq!(accumulator.finish(), DataValue::Number(EXACT_LIMIT as f64));
+
+        let mut accumulator = accumulator;
+        accumulator.add(EXACT_LIMIT, DataValue::String("one more".to_string()));
+        assert!(matches!(accumulator.state, State::Distinct(Distinct::Sketch(_))));
+        assert_close(accumulator.finish(), DataValue::Number((EXACT_LIMIT + 1) as f64), 0.05);
+    }
+
+    #[test]
+    fn merged_distinct_counts_switch_to_a_sketch_past_the_limit() {
+        let values = (0..3 * EXACT_LIMIT)
+            .map(|index| DataValue::Number((index % (2 * EXACT_LIMIT)) as f64))
+            .collect::<Vec<_>>();
+        // Parts below the limit on their own, exact and sketched parts
+        // merged either way round.
+        for chunk in [EXACT_LIMIT / 2, EXACT_LIMIT + 100, 2 * EXACT_LIMIT + 1] {
+            let accumulator = merged(Aggregation::CountDistinct, &values, chunk);
+            assert!(matches!(accumulator.state, State::Distinct(Distinct::Sketch(_))));
+            assert_close(accumulator.finish(), DataValue::Number((2 * EXACT_LIMIT) as f64), 0.05);
+        }
+    }
+
+    #[test]
+    fn percentiles_are_exact_up_to_the_limit() {
+        let values = numbers((0..EXACT_LIMIT).rev().map(|index| index as f64));
+        let accumulator = accumulate(Aggregation::Percentile(25), &values);
+        assert!(matches!(accumulator.state, State::Quantile(Quantiles::Exact(_))));
+        assert_eq!(accumulator.finish(), DataValue::Number(0.25 * (EXACT_LIMIT - 1) as f64));
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0], 50.0), Some(2.5));
+    }
+
+    #[test]
+    fn merged_percentiles_switch_to_a_sketch_past_the_limit() {
+        let count = 10 * EXACT_LIMIT;
+        let values = numbers((0..count).map(|index| ((index * 7919) % count) as f64));
+        for aggregation in [Aggregation::Median, Aggregation::Percentile(99)] {
+            let p = match aggregation {
+                Aggregation::Percentile(p) => f64::from(p),
+                _ => 50.0,
+            };
+            let expected = p / 100.0 * (count - 1) as f64;
+            for chunk in [EXACT_LIMIT / 4, EXACT_LIMIT + 1, count] {
+                let accumulator = merged(aggregation, &values, chunk);
+                assert!(matches!(accumulator.state, State::Quantile(Quantiles::Sketch(_))));
+                let estimate = accumulator.finish().as_f64().expect("a number");
+                assert!(
+                    (estimate - expected).abs() / (count as f64) < 0.005,
+                    "P{p} estimated as {estimate}, expected {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn first_last_and_mode_follow_row_order() {
+        let values = ["b", "a", "b", "a", "c"]
+            .into_iter()
+            .map(|text| DataValue::String(text.to_string()))
+            .collect::<Vec<_>>();
+        for chunk in [1, 2, 5] {
+            assert_eq!(merged(Aggregation::First, &values, chunk).finish(), values[0]);
+            assert_eq!(merged(Aggregation::Last, &values, chunk).finish(), values[4]);
+            // "a" and "b" tie; the value that sorts first wins.
+            assert_eq!(merged(Aggregation::Mode, &values, chunk).finish(), values[1]);
+        }
+        // Parts merged out of row order still keep the earliest and latest.
+        for aggregation in [Aggregation::First, Aggregation::Last] {
+            let mut late = Accumulator::new(aggregation);
+            late.add(3, values[3].clone());
+            let mut early = Accumulator::new(aggregation);
+            early.add(0, values[0].clone());
+            early.add(4, values[4].clone());
+            late.merge(early);
+            let expected = if aggregation == Aggregation::First { &values[0] } else { &values[4] };
+            assert_eq!(&late.finish(), expected);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::core::column::Column;
+use crate::query::model::NumericBins;
+
+/// Bin boundaries resolved against a column's values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinEdges {
+    /// Unbounded bins of a fixed width, aligned to zero.
+    Width(f64),
+    /// Ascending edges; the last bin includes its upper edge.
+    Edges(Vec<f64>),
+}
+
+impl BinEdges {
+    /// Explains why the bins cannot be built, e.g. a non-positive width or
+    /// a column without numbers.
+    pub fn resolve(bins: &NumericBins, column: &Column) -> Result<Self, String> {
+        let mut numbers = (0..column.len())
+            .filter_map(|row| column.number(row))
+            .filter(|value| value.is_finite())
+            .peekable();
+        if numbers.peek().is_none() {
+            return Err(format!("{} holds no numbers to bin", column.name));
+        }
+        // Only quantiles need the values themselves, sorted.
+        let mut edges = match bins {
+            NumericBins::Width(width) if *width > 0.0 && width.is_finite() => {
+                return Ok(BinEdges::Width(*width));
+            }
+            NumericBins::Width(_) => return Err("The bin width must be a positive number".to_string()),
+            NumericBins::Count(count) => {
+                let (min, max) = numbers.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+                    (min.min(value), max.max(value))
+                });
+                let count = (*count).max(1);
+                let width = (max - min) / f64::from(count);
+                (0..count)
+                    .map(|index| min + width * f64::from(index))
+                    .chain([max])
+                    .collect::<Vec<_>>()
+            }
+            NumericBins::Quantile(count) => {
+                let mut values = numbers.collect::<Vec<_>>();
+                values.sort_by(f64::total_cmp);
+                let count = (*count).max(1) as usize;
+                (0..count)
+                    .map(|index| values[index * values.len() / count])
+                    .chain(values.last().copied())
+                    .collect()
+            }
+            NumericBins::Edges(edges) => {
+                let mut edges = edges
+                    .iter()
+                    .copied()
+                    .filter(|edge| edge.is_finite())
+                    .collect::<Vec<_>>();
+                edges.sort_by(f64::total_cmp);
+                edges
+            }
+        };
+        edges.dedup();
+        if edges.len() < 2 {
+            return Err(format!("Binning {} needs at least two distinct bin edges", column.name));
+        }
+        Ok(BinEdges::Edges(edges))
+    }
+
+    /// The lower edge of the bin holding `value`.
+    pub fn lower_edge(&self, value: f64) -> Option<f64> {
+        match self {
+            BinEdges::Width(width) => Some((value / width).floor() * width),
+            BinEdges::Edges(edges) => {
+                let last = *edges.last()?;
+                if value < edges[0] || value > last {
+                    return None;
+                }
+                let index = edges.partition_point(|edge| *edge <= value);
+                Some(edges[index.saturating_sub(1).min(edges.len() - 2)])
+            }
+        }
+    }
+
+    /// The upper edge of the bin starting at `lower`.
+    pub fn upper_edge(&self, lower: f64) -> Option<f64> {
+        match self {
+            BinEdges::Width(width) => Some(lower + width),
+            BinEdges::Edges(edges) => {
+                let index = edges.iter().position(|edge| *edge == lower)?;
+                edges.get(index + 1).copied()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data::{DataType, DataValue};
+
+    fn amounts(values: &[f64]) -> Column {
+        let values = values.iter().copied().map(DataValue::Number).collect();
+        Column::new("Amount".to_string(), DataType::Number, values)
+    }
+
+    #[test]
+    fn each_kind_resolves_its_edges() {
+        let column = amounts(&[7.0, 1.0, 3.0, 9.0, 5.0]);
+        let resolve = |bins| BinEdges::resolve(&bins, &column);
+        assert_eq!(resolve(NumericBins::Width(2.5)), Ok(BinEdges::Width(2.5)));
+        assert_eq!(resolve(NumericBins::Count(4)), Ok(BinEdges::Edges(vec![1.0, 3.0, 5.0, 7.0, 9.0])));
+        assert_eq!(resolve(NumericBins::Quantile(2)), Ok(BinEdges::Edges(vec![1.0, 5.0, 9.0])));
+        let edges = NumericBins::Edges(vec![10.0, 0.0, 5.0, 5.0]);
+        assert_eq!(resolve(edges), Ok(BinEdges::Edges(vec![0.0, 5.0, 10.0])));
+        let expected = Err("The bin width must be a positive number".to_string());
+        assert_eq!(resolve(NumericBins::Width(0.0)), expected);
+    }
+
+    #[test]
+    fn the_last_bin_includes_its_upper_edge() {
+        let edges = BinEdges::Edges(vec![0.0, 5.0, 10.0]);
+        assert_eq!(edges.lower_edge(5.0), Some(5.0));
+        assert_eq!(edges.lower_edge(10.0), Some(5.0));
+        assert_eq!(edges.lower_edge(10.5), None);
+        assert_eq!(BinEdges::Width(5.0).lower_edge(-0.5), Some(-5.0));
+    }
+}
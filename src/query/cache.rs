@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::Local;
+
+use crate::query::engine::QueryResult;
+use crate::query::model::{Predicate, QuerySpec};
+
+/// Results kept before the least recently used one is dropped.
+const CACHE_CAPACITY: usize = 32;
+
+/// What a cached result depends on: the sheet it was computed from, the
+/// version of that sheet's data, and the query.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub sheet: String,
+    /// Bumped by the owner whenever the sheet's data is rebuilt.
+    pub version: u64,
+    spec: String,
+}
+
+impl CacheKey {
+    pub fn new(sheet: &str, version: u64, spec: &QuerySpec) -> Self {
+        Self {
+            sheet: sheet.to_string(),
+            version,
+            spec: normalized_spec(spec),
+        }
+    }
+}
+
+/// `spec` as JSON, with differences that cannot change the result removed:
+/// filters are combined with AND so their order does not matter, and
+/// neither does the order or repetition of values in a list filter. Specs
+/// with a relative date window also depend on today's date.
+fn normalized_spec(spec: &QuerySpec) -> String {
+    let mut spec = spec.clone();
+    let mut relative_dates = false;
+    for filter in &mut spec.filters {
+        match &mut filter.predicate {
+            Predicate::In(values) | Predicate::NotIn(values) => {
+                values.sort();
+                values.dedup();
+            }
+            Predicate::LastDays(_) => relative_dates = true,
+            _ => {}
+        }
+    }
+    let mut filters = spec
+        .filters
+        .iter()
+        .map(|filter| serde_json::to_string(filter).unwrap_or_default())
+        .collect::<Vec<_>>();
+    filters.sort();
+    spec.filters.clear();
+    let mut normalized = serde_json::to_string(&spec).unwrap_or_default();
+    for filter in filters {
+        normalized.push('\n');
+        normalized.push_str(&filter);
+    }
+    if relative_dates {
+        normalized.push_str(&format!("\n@{}", Local::now().date_naive()));
+    }
+    normalized
+}
+
+/// Memoizes query results so returning to an earlier query, or redrawing
+/// the current one, does not scan the sheet again.
+#[derive(Debug, Default)]
+pub struct QueryCache {
+    /// Most recently used last.
+    entries: VecDeque<(CacheKey, Arc<QueryResult>)>,
+}
+
+impl QueryCache {
+    pub fn get(&mut self, key: &CacheKey) -> Option<Arc<QueryResult>> {
+        let position = self.entries.iter().position(|(cached, _)| cached == key)?;
+        let entry = self.entries.remove(position)?;
+        let result = Arc::clone(&entry.1);
+        self.entries.push_back(entry);
+        Some(result)
+    }
+
+    pub fn insert(&mut self, key: CacheKey, result: Arc<QueryResult>) {
+        self.entries.retain(|(cached, _)| *cached != key);
+        self.entries.push_back((key, result));
+        while self.entries.len() > CACHE_CAPACITY {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Drops results computed from older versions of `sheet`.
+    pub fn invalidate_sheet(&mut self, sheet: &str, version: u64) {
+        self.entries
+            .retain(|(cached, _)| cached.sheet != sheet || cached.version == version);
+    }
+
+    /// Drops everything, for when the data file is reloaded.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::model::Filter;
+
+    fn filter(column: &str, predicate: Predicate) -> Filter {
+        Filter {
+            column: column.to_string(),
+            predicate,
+        }
+    }
+
+    fn listed(values: &[&str]) -> Predicate {
+        Predicate::In(values.iter().map(|value| value.to_string()).collect())
+    }
+
+    fn spec(filters: Vec<Filter>) -> QuerySpec {
+        QuerySpec {
+            filters,
+            ..QuerySpec::empty()
+        }
+    }
+
+    fn result() -> Arc<QueryResult> {
+        Arc::new(QueryResult::empty())
+    }
+
+    #[test]
+    fn filter_and_list_order_do_not_change_the_key() {
+        let first = spec(vec![
+            filter("Region", listed(&["North", "South", "North"])),
+            filter("Amount", Predicate::NotEmpty),
+        ]);
+        let second = spec(vec![
+            filter("Amount", Predicate::NotEmpty),
+            filter("Region", listed(&["South", "North"])),
+        ]);
+        assert_eq!(CacheKey::new("Sheet", 1, &first), CacheKey::new("Sheet", 1, &second));
+
+        let narrower = spec(vec![filter("Region", listed(&["North"]))]);
+        assert_ne!(CacheKey::new("Sheet", 1, &first), CacheKey::new("Sheet", 1, &narrower));
+    }
+
+    #[test]
+    fn a_new_version_misses_and_invalidation_drops_older_ones() {
+        let mut cache = QueryCache::default();
+        let query = spec(Vec::new());
+        cache.insert(CacheKey::new("Sheet", 1, &query), result());
+        cache.insert(CacheKey::new("Other", 1, &query), result());
+        assert!(cache.get(&CacheKey::new("Sheet", 1, &query)).is_some());
+        assert!(cache.get(&CacheKey::new("Sheet", 2, &query)).is_none());
+
+        cache.insert(CacheKey::new("Sheet", 2, &query), result());
+        cache.invalidate_sheet("Sheet", 2);
+        assert!(cache.get(&CacheKey::new("Sheet", 1, &query)).is_none());
+        assert!(cache.get(&CacheKey::new("Sheet", 2, &query)).is_some());
+        assert!(cache.get(&CacheKey::new("Other", 1, &query)).is_some());
+    }
+
+    #[test]
+    fn the_least_recently_used_result_is_evicted_at_capacity() {
+        let mut cache = QueryCache::default();
+        let key = |version: usize| CacheKey::new("Sheet", version as u64, &spec(Vec::new()));
+        for version in 0..CACHE_CAPACITY {
+            cache.insert(key(version), result());
+        }
+        // Reading the oldest entry makes the second oldest the one to go.
+        assert!(cache.get(&key(0)).is_some());
+        cache.insert(key(CACHE_CAPACITY), result());
+        assert_eq!(cache.entries.len(), CACHE_CAPACITY);
+        assert!(cache.get(&key(0)).is_some());
+        assert!(cache.get(&key(1)).is_none());
+        assert!(cache.get(&key(CACHE_CAPACITY)).is_some());
+    }
+}
@@ -1,108 +1,1076 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::thread;
 
-use crate::core::data::{Column, DataValue};
+use crate::calc::aggregate::AggregateCalc;
+use crate::calc::eval::eval;
+use crate::calc::field::CalculatedField;
+use crate::core::column::Column;
+use crate::core::data::{DataType, DataValue};
 use crate::core::workbook::Worksheet;
-use crate::query::model::{Aggregation, QuerySpec};
+use crate::query::aggregate::Accumulator;
+use crate::query::bins::BinEdges;
+use crate::query::filter::FilterMatcher;
+use crate::query::model::{
+    DateGrouping, Dimension, Filter, Limit, LimitDirection, Measure, QuerySpec, SortDirection, SortField, SortKey,
+    SortOrder,
+};
+use crate::query::table_calc::apply_table_calc;
+
+const OTHER_LABEL: &str = "Other";
+/// Rows scanned between calls to the progress callback.
+const PROGRESS_INTERVAL: usize = 4096;
+/// Matching rows grouped together before partial groups are merged; also
+/// the unit of work handed to each grouping thread.
+const PARTITION_ROWS: usize = 65_536;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldRole {
+    Dimension,
+    Measure,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResultField {
+    pub name: String,
+    pub data_type: DataType,
+    pub role: FieldRole,
+    /// Set for binned dimensions, whose values are the bins' lower edges.
+    pub bins: Option<BinEdges>,
+}
 
 #[derive(Debug, Clone)]
 pub struct QueryResult {
-    pub headers: Vec<String>,
-    pub rows: Vec<Vec<String>>,
+    pub fields: Vec<ResultField>,
+    pub rows: Vec<Vec<DataValue>>,
+    /// Why the query could not run as specified; the result is empty then.
+    pub error: Option<String>,
 }
 
-pub fn run_query(sheet: &Worksheet, spec: &QuerySpec) -> QueryResult {
-    if spec.rows.is_empty() && spec.columns.is_empty() {
-        return preview_table(sheet);
+impl QueryResult {
+    pub fn empty() -> Self {
+        Self {
+            fields: Vec::new(),
+            rows: Vec::new(),
+            error: None,
+        }
     }
 
-    let row_columns = select_columns(sheet, &spec.rows);
-    let measure_columns = select_columns(sheet, &spec.columns);
+    fn failed(error: String) -> Self {
+        Self {
+            error: Some(error),
+            ..Self::empty()
+        }
+    }
 
-    let mut groups: HashMap<Vec<String>, Vec<Vec<f64>>> = HashMap::new();
-    let row_count = sheet.row_count;
+    pub fn headers(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(|field| field.name.as_str())
+    }
 
-    for row_index in 0..row_count {
-        let mut row_key = Vec::new();
-        for column in &row_columns {
-            row_key.push(value_at(column, row_index).display_string());
-        }
+    pub fn dimension_indices(&self) -> Vec<usize> {
+        self.indices_with_role(FieldRole::Dimension)
+    }
 
-        let entry = groups.entry(row_key).or_insert_with(|| {
-            vec![Vec::new(); measure_columns.len()]
-        });
+    pub fn measure_indices(&self) -> Vec<usize> {
+        self.indices_with_role(FieldRole::Measure)
+    }
 
-        for (idx, column) in measure_columns.iter().enumerate() {
-            if let Some(value) = value_at(column, row_index).as_f64() {
-                entry[idx].push(value);
-            }
+    /// Joins the dimension values of a row into a single display label.
+    pub fn row_label(&self, row: &[DataValue]) -> String {
+        self.dimension_indices()
+            .into_iter()
+            .filter_map(|idx| row.get(idx))
+            .filter(|value| !matches!(value, DataValue::Empty))
+            .map(|value| value.display_string())
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+
+    fn indices_with_role(&self, role: FieldRole) -> Vec<usize> {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| field.role == role)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+}
+
+/// Typed dimension values of a group alongside the running aggregation
+/// state of each measure and each aggregate calculation slot.
+struct Group {
+    key_values: Vec<DataValue>,
+    states: Vec<Accumulator>,
+    /// The sheet row the group starts at.
+    first_row: usize,
+}
+
+impl Group {
+    /// Folds in `other`'s states, which cover rows after this group's own.
+    fn merge(&mut self, other: Group) {
+        for (state, other) in self.states.iter_mut().zip(other.states) {
+            state.merge(other);
         }
     }
+}
 
-    let mut headers = spec.rows.clone();
-    for measure in &spec.columns {
-        for agg in &spec.aggregations {
-            headers.push(format!("{measure} ({:?})", agg));
+/// Groups `rows`, which are split into blocks of `PARTITION_ROWS` that are
+/// grouped separately, on up to `threads` threads, and merged in block
+/// order. Serial and parallel runs therefore see the same values in the
+/// same order and produce the same result.
+fn group_rows(
+    sheet: &Worksheet,
+    layout: &Layout,
+    rows: &[usize],
+    threads: usize,
+    progress: &(dyn Fn(f32) -> bool + Sync),
+) -> Option<Vec<Group>> {
+    let scanned = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+    let tick = || {
+        let done = scanned.fetch_add(PROGRESS_INTERVAL, AtomicOrdering::Relaxed) + PROGRESS_INTERVAL;
+        if cancelled.load(AtomicOrdering::Relaxed)
+            || !progress(0.5 + 0.5 * done.min(rows.len()) as f32 / rows.len() as f32)
+        {
+            cancelled.store(true, AtomicOrdering::Relaxed);
+            return false;
         }
+        true
+    };
+    if !progress(0.5) {
+        return None;
     }
 
-    let mut rows = Vec::new();
-    for (key, values) in groups {
-        let mut row = key;
-        for series in values {
-            for agg in &spec.aggregations {
-                row.push(match agg {
-                    Aggregation::Sum => series.iter().sum::<f64>().to_string(),
-                    Aggregation::Avg => {
-                        if series.is_empty() {
-                            "0".to_string()
-                        } else {
-                            (series.iter().sum::<f64>() / series.len() as f64).to_string()
+    let partitions = rows.chunks(PARTITION_ROWS).collect::<Vec<_>>();
+    let threads = threads.clamp(1, partitions.len().max(1));
+    let mut partials = if threads == 1 {
+        partitions
+            .iter()
+            .map(|partition| group_partition(sheet, layout, partition, &tick))
+            .collect::<Option<Vec<_>>>()?
+    } else {
+        // Threads take the next unclaimed block until none are left.
+        let next = AtomicUsize::new(0);
+        let claimed = thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, AtomicOrdering::Relaxed);
+                            let Some(partition) = partitions.get(index) else {
+                                return Some(done);
+                            };
+                            done.push((index, group_partition(sheet, layout, partition, &tick)?));
                         }
-                    }
-                    Aggregation::Count => series.len().to_string(),
-                    Aggregation::Min => series
-                        .iter()
-                        .cloned()
-                        .reduce(f64::min)
-                        .unwrap_or(0.0)
-                        .to_string(),
-                    Aggregation::Max => series
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect::<Option<Vec<_>>>()
+        })?;
+        let mut claimed = claimed.into_iter().flatten().collect::<Vec<_>>();
+        claimed.sort_by_key(|(index, _)| *index);
+        claimed.into_iter().map(|(_, groups)| groups).collect()
+    };
+    if partials.len() == 1 {
+        return partials.pop();
+    }
+
+    let mut indices: HashMap<Vec<HashedValue>, usize> = HashMap::new();
+    let mut merged: Vec<Group> = Vec::new();
+    for group in partials.into_iter().flatten() {
+        let key = group
+            .key_values
+            .iter()
+            .cloned()
+            .map(HashedValue)
+            .collect::<Vec<_>>();
+        match indices.get(&key) {
+            Some(&index) => merged[index].merge(group),
+            None => {
+                indices.insert(key, merged.len());
+                merged.push(group);
+            }
+        }
+    }
+    Some(merged)
+}
+
+/// Groups one block of rows in row order. Rows are grouped by one integer
+/// code per dimension; each group's typed dimension values are only built
+/// for its first row.
+fn group_partition(
+    sheet: &Worksheet,
+    layout: &Layout,
+    rows: &[usize],
+    tick: &dyn Fn() -> bool,
+) -> Option<Vec<Group>> {
+    let mut interned = layout
+        .dimensions
+        .iter()
+        .map(|_| HashMap::new())
+        .collect::<Vec<_>>();
+    let mut group_indices: HashMap<Vec<u64>, usize> = HashMap::new();
+    let mut groups: Vec<Group> = Vec::new();
+    let mut key = Vec::with_capacity(layout.dimensions.len());
+
+    for (position, &row_index) in rows.iter().enumerate() {
+        if (position + 1) % PROGRESS_INTERVAL == 0 && !tick() {
+            return None;
+        }
+        key.clear();
+        key.extend(
+            layout
+                .dimensions
+                .iter()
+                .zip(&mut interned)
+                .map(|(dimension, codes)| dimension.key(row_index, codes)),
+        );
+        let index = match group_indices.get(key.as_slice()) {
+            Some(&index) => index,
+            None => {
+                group_indices.insert(key.clone(), groups.len());
+                groups.push(Group {
+                    key_values: layout
+                        .dimensions
                         .iter()
-                        .cloned()
-                        .reduce(f64::max)
-                        .unwrap_or(0.0)
-                        .to_string(),
+                        .map(|dimension| dimension.value(row_index))
+                        .collect(),
+                    states: layout.accumulators(),
+                    first_row: row_index,
                 });
+                groups.len() - 1
+            }
+        };
+        layout.collect(sheet, row_index, &mut groups[index].states);
+    }
+    Some(groups)
+}
+
+/// A grouping value usable as a hash key; numbers compare by bit pattern.
+struct HashedValue(DataValue);
+
+impl PartialEq for HashedValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (DataValue::Number(left), DataValue::Number(right))
+            | (DataValue::Duration(left), DataValue::Duration(right)) => {
+                left.to_bits() == right.to_bits()
             }
+            (left, right) => left == right,
         }
-        rows.push(row);
     }
+}
+
+impl Eq for HashedValue {}
+
+impl Hash for HashedValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.0).hash(state);
+        match &self.0 {
+            DataValue::String(text) | DataValue::Error(text) => text.hash(state),
+            DataValue::Number(number) | DataValue::Duration(number) => number.to_bits().hash(state),
+            DataValue::Date(date) => date.hash(state),
+            DataValue::DateTime(datetime) => datetime.hash(state),
+            DataValue::Time(time) => time.hash(state),
+            DataValue::Boolean(flag) => flag.hash(state),
+            DataValue::Empty => {}
+        }
+    }
+}
+
+/// A Rows shelf dimension resolved against its column.
+struct GroupedDimension<'a> {
+    dimension: &'a Dimension,
+    column: &'a Column,
+    bins: Option<BinEdges>,
+}
+
+impl GroupedDimension<'_> {
+    /// The grouping value for a row: date parts become numbers, truncations
+    /// stay dates and bins become their lower edge, so all of them order
+    /// naturally.
+    fn value(&self, row_index: usize) -> DataValue {
+        let value = self.column.value(row_index);
+        if let Some(bins) = &self.bins {
+            return value
+                .as_f64()
+                .and_then(|number| bins.lower_edge(number))
+                .map_or(DataValue::Empty, DataValue::Number);
+        }
+        match (self.dimension.date, value) {
+            (Some(DateGrouping::Part(part)), DataValue::Date(date)) => {
+                DataValue::Number(part.extract(date) as f64)
+            }
+            (Some(DateGrouping::Part(part)), DataValue::DateTime(datetime)) => {
+                DataValue::Number(part.extract_datetime(datetime) as f64)
+            }
+            (Some(DateGrouping::Truncate(part)), DataValue::Date(date)) => {
+                DataValue::Date(part.truncate(date))
+            }
+            (Some(DateGrouping::Truncate(part)), DataValue::DateTime(datetime)) => {
+                DataValue::DateTime(part.truncate_datetime(datetime))
+            }
+            (_, value) => value,
+        }
+    }
+
+    /// A code standing for the row's grouping value. Plain text uses the
+    /// column's dictionary code; other values are numbered in `interned`
+    /// from 2^32 up, so the two never collide.
+    fn key(&self, row_index: usize, interned: &mut HashMap<HashedValue, u64>) -> u64 {
+        if self.bins.is_none()
+            && let Some(code) = self.column.text_code(row_index)
+        {
+            return u64::from(code);
+        }
+        let next = (1 << 32) + interned.len() as u64;
+        *interned
+            .entry(HashedValue(self.value(row_index)))
+            .or_insert(next)
+    }
+
+    fn field(&self) -> ResultField {
+        let data_type = match (self.dimension.date, &self.column.data_type) {
+            _ if self.bins.is_some() => DataType::Number,
+            (Some(DateGrouping::Part(_)), DataType::Date | DataType::DateTime) => DataType::Number,
+            _ => self.column.data_type.clone(),
+        };
+        ResultField {
+            name: self.dimension.label(),
+            data_type,
+            role: FieldRole::Dimension,
+            bins: self.bins.clone(),
+        }
+    }
+}
+
+/// The shelves resolved against a sheet: which columns group the rows and
+/// which values are aggregated for each group.
+struct Layout<'a> {
+    dimensions: Vec<GroupedDimension<'a>>,
+    measures: Vec<(&'a Measure, &'a Column)>,
+    calculations: Vec<(&'a CalculatedField, AggregateCalc)>,
+}
+
+impl Layout<'_> {
+    /// Empty states for the measures, then every calculation slot.
+    fn accumulators(&self) -> Vec<Accumulator> {
+        self.measures
+            .iter()
+            .map(|(measure, _)| measure.aggregation)
+            .chain(
+                self.calculations
+                    .iter()
+                    .flat_map(|(_, calc)| calc.slots.iter().map(|slot| slot.aggregation)),
+            )
+            .map(Accumulator::new)
+            .collect()
+    }
+
+    fn collect(&self, sheet: &Worksheet, row_index: usize, states: &mut [Accumulator]) {
+        let values = self
+            .measures
+            .iter()
+            .map(|(_, column)| column.value(row_index))
+            .chain(self.calculations.iter().flat_map(|(_, calc)| {
+                calc.slots.iter().map(|slot| {
+                    eval(&slot.expr, &|index| sheet.columns[index].value(row_index))
+                })
+            }));
+        for (state, value) in states.iter_mut().zip(values) {
+            if !value.is_null() {
+                state.add(row_index, value);
+            }
+        }
+    }
+
+    fn fields(&self) -> Vec<ResultField> {
+        let mut fields = self
+            .dimensions
+            .iter()
+            .map(GroupedDimension::field)
+            .collect::<Vec<_>>();
+        for (measure, column) in &self.measures {
+            fields.push(ResultField {
+                name: measure.label(),
+                data_type: measure_type(measure, column),
+                role: FieldRole::Measure,
+                bins: None,
+            });
+        }
+        for (field, calc) in &self.calculations {
+            fields.push(ResultField {
+                name: field.name.clone(),
+                data_type: calc.data_type.clone(),
+                role: FieldRole::Measure,
+                bins: None,
+            });
+        }
+        fields
+    }
+
+    fn row(&self, group: &Group) -> Vec<DataValue> {
+        let mut row = group.key_values.clone();
+        let mut states = group.states.iter();
+        for ((measure, column), state) in self.measures.iter().zip(states.by_ref()) {
+            row.push(match (state.finish(), measure_type(measure, column)) {
+                (DataValue::Number(seconds), DataType::Duration) => DataValue::Duration(seconds),
+                (value, _) => value,
+            });
+        }
+        for (_, calc) in &self.calculations {
+            let slot_values = calc
+                .slots
+                .iter()
+                .zip(states.by_ref())
+                .map(|(_, state)| state.finish())
+                .collect::<Vec<_>>();
+            row.push(calc.evaluate(&slot_values));
+        }
+        row
+    }
+
+    /// Locates the result column produced for a sort field, if it is on a
+    /// shelf.
+    fn result_index(&self, field: &SortField) -> Option<usize> {
+        match field {
+            SortField::Dimension(name) => {
+                self.dimensions
+                    .iter()
+                    .position(|grouped| &grouped.dimension.label() == name)
+            }
+            SortField::Measure {
+                column,
+                aggregation,
+            } => {
+                let measure = self.measures.iter().position(|(measure, _)| {
+                    &measure.column == column && measure.aggregation == *aggregation
+                })?;
+                Some(self.dimensions.len() + measure)
+            }
+            SortField::Calculation(name) => {
+                let calculation = self
+                    .calculations
+                    .iter()
+                    .position(|(field, _)| &field.name == name)?;
+                Some(self.dimensions.len() + self.measures.len() + calculation)
+            }
+        }
+    }
+}
+
+/// The type of a measure's values: First, Last and Mode keep the column's
+/// type, totals and extremes of durations stay durations, and everything
+/// else, including table calculations, is a number.
+fn measure_type(measure: &Measure, column: &Column) -> DataType {
+    match &column.data_type {
+        _ if measure.table_calc.is_some() => DataType::Number,
+        data_type if measure.aggregation.preserves_type() => data_type.clone(),
+        DataType::Duration if measure.aggregation.keeps_duration() => DataType::Duration,
+        _ => DataType::Number,
+    }
+}
 
-    QueryResult { headers, rows }
+/// Runs `spec` against `sheet` on every available core. `progress` is
+/// called every few thousand rows with the fraction scanned so far;
+/// returning false abandons the query.
+pub fn run_query(
+    sheet: &Worksheet,
+    spec: &QuerySpec,
+    progress: &(dyn Fn(f32) -> bool + Sync),
+) -> Option<QueryResult> {
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    run_query_with_threads(sheet, spec, progress, threads)
 }
 
-fn preview_table(sheet: &Worksheet) -> QueryResult {
-    let headers = sheet.column_names();
+/// `run_query` grouping on at most `threads` threads. The result does not
+/// depend on the thread count.
+pub fn run_query_with_threads(
+    sheet: &Worksheet,
+    spec: &QuerySpec,
+    progress: &(dyn Fn(f32) -> bool + Sync),
+    threads: usize,
+) -> Option<QueryResult> {
+    let filters = match select_filters(sheet, &spec.filters) {
+        Ok(filters) => filters,
+        Err(error) => return Some(QueryResult::failed(error)),
+    };
+    let mut matching_rows = filter_rows(sheet, &filters, progress)?;
+
+    if spec.rows.is_empty() && spec.columns.is_empty() && spec.calculations.is_empty() {
+        sort_preview_rows(sheet, &mut matching_rows, &spec.sort);
+        return Some(preview_table(sheet, &matching_rows));
+    }
+
+    let dimensions = match select_dimensions(sheet, &spec.rows) {
+        Ok(dimensions) => dimensions,
+        Err(error) => return Some(QueryResult::failed(error)),
+    };
+    let measures = match select_measures(sheet, &spec.columns) {
+        Ok(measures) => measures,
+        Err(error) => return Some(QueryResult::failed(error)),
+    };
+    let layout = Layout {
+        dimensions,
+        measures,
+        calculations: compile_calculations(sheet, &spec.calculations),
+    };
+
+    let groups = group_rows(sheet, &layout, &matching_rows, threads, progress)?;
+    let mut groups = groups
+        .into_iter()
+        .map(|group| (layout.row(&group), group))
+        .collect::<Vec<_>>();
+    let mut other_row = match &spec.limit {
+        Some(limit) => apply_limit(&mut groups, limit, &layout),
+        None => None,
+    };
+
+    let mut rows = groups.into_iter().map(|(row, _)| row).collect::<Vec<_>>();
+
+    let sort_columns = spec
+        .sort
+        .iter()
+        .filter_map(|key| Some((layout.result_index(&key.field)?, key)))
+        .collect::<Vec<_>>();
+    let dimension_count = layout.dimensions.len();
+    rows.sort_by(|left, right| {
+        sort_columns
+            .iter()
+            .map(|(index, key)| compare_sorted(&left[*index], &right[*index], key))
+            .chain((0..dimension_count).map(|index| left[index].compare(&right[index])))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+
+    // The "Other" row is not a group of its own, so table calculations skip
+    // it and leave its cells empty.
+    for (offset, (measure, _)) in layout.measures.iter().enumerate() {
+        if let Some(table_calc) = &measure.table_calc {
+            let index = dimension_count + offset;
+            apply_table_calc(&mut rows, index, dimension_count, table_calc);
+            if let Some(other) = &mut other_row {
+                other[index] = DataValue::Empty;
+            }
+        }
+    }
+    rows.extend(other_row);
+
+    Some(QueryResult {
+        fields: layout.fields(),
+        rows,
+        error: None,
+    })
+}
+
+/// Keeps the groups ranked within the limit and, when requested, returns
+/// the rest merged into a single "Other" row. Limits on fields that are not
+/// on a shelf leave the groups untouched.
+fn apply_limit(
+    groups: &mut Vec<(Vec<DataValue>, Group)>,
+    limit: &Limit,
+    layout: &Layout,
+) -> Option<Vec<DataValue>> {
+    let rank_index = layout.result_index(&limit.by)?;
+    let key = SortKey {
+        field: limit.by.clone(),
+        direction: match limit.direction {
+            LimitDirection::Top => SortDirection::Descending,
+            LimitDirection::Bottom => SortDirection::Ascending,
+        },
+        order: SortOrder::Natural,
+    };
+
+    let dimension_count = layout.dimensions.len();
+    groups.sort_by(|(left, _), (right, _)| {
+        compare_sorted(&left[rank_index], &right[rank_index], &key).then_with(|| {
+            (0..dimension_count)
+                .map(|index| left[index].compare(&right[index]))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+    });
+
+    let rest = groups.split_off(limit.count.min(groups.len()));
+    if !limit.other_bucket || rest.is_empty() {
+        return None;
+    }
+    let mut other = Group {
+        key_values: (0..dimension_count)
+            .map(|index| match index {
+                0 => DataValue::String(OTHER_LABEL.to_string()),
+                _ => DataValue::Empty,
+            })
+            .collect(),
+        states: layout.accumulators(),
+        first_row: 0,
+    };
+    // Merging expects later rows, so the cut groups go in in row order
+    // rather than rank order.
+    let mut rest = rest.into_iter().map(|(_, group)| group).collect::<Vec<_>>();
+    rest.sort_by_key(|group| group.first_row);
+    for group in rest {
+        other.merge(group);
+    }
+    Some(layout.row(&other))
+}
+
+/// Compares two values under a sort key; empty values always sort last.
+fn compare_sorted(left: &DataValue, right: &DataValue, key: &SortKey) -> Ordering {
+    match (left, right) {
+        (DataValue::Empty, DataValue::Empty) => return Ordering::Equal,
+        (DataValue::Empty, _) => return Ordering::Greater,
+        (_, DataValue::Empty) => return Ordering::Less,
+        _ => {}
+    }
+    let ordering = match &key.order {
+        SortOrder::Natural => left.compare(right),
+        SortOrder::Manual(order) => {
+            let position = |value: &DataValue| {
+                let text = value.display_string();
+                order.iter().position(|item| *item == text)
+            };
+            match (position(left), position(right)) {
+                (Some(left_position), Some(right_position)) => left_position.cmp(&right_position),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => left.compare(right),
+            }
+        }
+    };
+    match key.direction {
+        SortDirection::Ascending => ordering,
+        SortDirection::Descending => ordering.reverse(),
+    }
+}
+
+fn preview_table(sheet: &Worksheet, matching_rows: &[usize]) -> QueryResult {
+    let fields = sheet
+        .columns
+        .iter()
+        .map(|column| ResultField {
+            name: column.name.clone(),
+            data_type: column.data_type.clone(),
+            role: match column.data_type {
+                DataType::Number => FieldRole::Measure,
+                _ => FieldRole::Dimension,
+            },
+            bins: None,
+        })
+        .collect();
     let mut rows = Vec::new();
-    let row_count = sheet.row_count.min(25);
-    for row_index in 0..row_count {
+    for &row_index in matching_rows.iter().take(25) {
         let mut row = Vec::new();
         for column in &sheet.columns {
-            row.push(value_at(column, row_index).display_string());
+            row.push(column.value(row_index));
         }
         rows.push(row);
     }
-    QueryResult { headers, rows }
+    QueryResult {
+        fields,
+        rows,
+        error: None,
+    }
+}
+
+/// Orders raw rows by any dimension sort keys; measure keys need grouping
+/// and are ignored here.
+fn sort_preview_rows(sheet: &Worksheet, row_indices: &mut [usize], sort: &[SortKey]) {
+    let sort_columns = sort
+        .iter()
+        .filter_map(|key| match &key.field {
+            SortField::Dimension(name) => sheet
+                .columns
+                .iter()
+                .find(|column| &column.name == name)
+                .map(|column| (column, key)),
+            SortField::Measure { .. } | SortField::Calculation(_) => None,
+        })
+        .collect::<Vec<_>>();
+    if sort_columns.is_empty() {
+        return;
+    }
+    row_indices.sort_by(|&left, &right| {
+        sort_columns
+            .iter()
+            .map(|(column, key)| {
+                compare_sorted(&column.value(left), &column.value(right), key)
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// A filter resolved against its column. On text columns the predicate is
+/// checked once per dictionary entry instead of once per row.
+struct RowFilter<'a> {
+    matcher: FilterMatcher,
+    column: &'a Column,
+    accepted_codes: Option<Vec<bool>>,
+}
+
+impl<'a> RowFilter<'a> {
+    fn new(matcher: FilterMatcher, column: &'a Column) -> Self {
+        let accepted_codes = column.dictionary().map(|dictionary| {
+            dictionary
+                .iter()
+                .map(|text| matcher.matches(&DataValue::String(text.clone())))
+                .collect()
+        });
+        Self {
+            matcher,
+            column,
+            accepted_codes,
+        }
+    }
+
+    fn matches(&self, row_index: usize) -> bool {
+        match (&self.accepted_codes, self.column.text_code(row_index)) {
+            (Some(accepted), Some(code)) => accepted[code as usize],
+            _ => self.matcher.matches(&self.column.value(row_index)),
+        }
+    }
 }
 
-fn select_columns<'a>(sheet: &'a Worksheet, names: &[String]) -> Vec<&'a Column> {
+/// Binds each filter to its column. A filter on a field the sheet does not
+/// have, such as one saved before the field was renamed, fails the query
+/// rather than matching every row.
+fn select_filters<'a>(sheet: &'a Worksheet, filters: &[Filter]) -> Result<Vec<RowFilter<'a>>, String> {
+    filters
+        .iter()
+        .map(|filter| {
+            let column = sheet
+                .columns
+                .iter()
+                .find(|column| column.name == filter.column)
+                .ok_or_else(|| format!("The filter on {} refers to a missing field", filter.column))?;
+            Ok(RowFilter::new(FilterMatcher::new(&filter.predicate), column))
+        })
+        .collect()
+}
+
+fn filter_rows(
+    sheet: &Worksheet,
+    filter_columns: &[RowFilter],
+    progress: &dyn Fn(f32) -> bool,
+) -> Option<Vec<usize>> {
+    let mut rows = Vec::new();
+    for row_index in 0..sheet.row_count {
+        if row_index % PROGRESS_INTERVAL == 0
+            && !progress(0.5 * row_index as f32 / sheet.row_count as f32)
+        {
+            return None;
+        }
+        if filter_columns
+            .iter()
+            .all(|filter| filter.matches(row_index))
+        {
+            rows.push(row_index);
+        }
+    }
+    Some(rows)
+}
+
+/// Binds each Columns shelf measure to its column, failing on a field the
+/// sheet does not have.
+fn select_measures<'a>(
+    sheet: &'a Worksheet,
+    measures: &'a [Measure],
+) -> Result<Vec<(&'a Measure, &'a Column)>, String> {
+    measures
+        .iter()
+        .map(|measure| Ok((measure, find_column(sheet, &measure.column)?)))
+        .collect()
+}
+
+fn find_column<'a>(sheet: &'a Worksheet, name: &str) -> Result<&'a Column, String> {
     sheet
         .columns
         .iter()
-        .filter(|column| names.contains(&column.name))
+        .find(|column| column.name == name)
+        .ok_or_else(|| format!("Field {name} not found"))
+}
+
+/// Compiles the aggregate calculations against the sheet, skipping any that
+/// fail; the editor reports their errors.
+fn compile_calculations<'a>(
+    sheet: &Worksheet,
+    calculations: &'a [CalculatedField],
+) -> Vec<(&'a CalculatedField, AggregateCalc)> {
+    calculations
+        .iter()
+        .filter_map(|field| Some((field, AggregateCalc::compile(field, &sheet.columns).ok()?)))
         .collect()
 }
 
-fn value_at(column: &Column, index: usize) -> DataValue {
-    column.values.get(index).cloned().unwrap_or(DataValue::Empty)
+/// Bins are resolved against every value of the column, so filters do not
+/// move the bin edges. Fails when a dimension's field is missing or its bins
+/// cannot be resolved.
+fn select_dimensions<'a>(
+    sheet: &'a Worksheet,
+    dimensions: &'a [Dimension],
+) -> Result<Vec<GroupedDimension<'a>>, String> {
+    let mut selected = Vec::with_capacity(dimensions.len());
+    for dimension in dimensions {
+        let column = find_column(sheet, &dimension.column)?;
+        let bins = dimension
+            .bins
+            .as_ref()
+            .map(|bins| BinEdges::resolve(bins, column))
+            .transpose()?;
+        selected.push(GroupedDimension {
+            dimension,
+            column,
+            bins,
+        });
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::workbook::SheetDiagnostics;
+    use crate::query::model::{Aggregation, NumericBins, Predicate, TableCalc, TableCalcKind, TableCalcScope};
+
+    fn sheet(columns: Vec<Column>) -> Worksheet {
+        Worksheet {
+            name: "Test".to_string(),
+            row_count: columns.first().map_or(0, Column::len),
+            columns,
+            diagnostics: SheetDiagnostics::default(),
+        }
+    }
+
+    fn texts(values: &[&str]) -> Vec<DataValue> {
+        values.iter().map(|value| DataValue::String(value.to_string())).collect()
+    }
+
+    fn numbers(values: &[f64]) -> Vec<DataValue> {
+        values.iter().copied().map(DataValue::Number).collect()
+    }
+
+    #[test]
+    fn table_calcs_skip_the_other_row() {
+        let sheet = sheet(vec![
+            Column::new("Region".to_string(), DataType::String, texts(&["A", "B", "C", "D"])),
+            Column::new("Amount".to_string(), DataType::Number, numbers(&[10.0, 5.0, 3.0, 2.0])),
+        ]);
+        let measure = Measure {
+            table_calc: Some(TableCalc {
+                kind: TableCalcKind::PercentOfTotal,
+                scope: TableCalcScope::Table,
+            }),
+            ..Measure::new("Amount".to_string(), Aggregation::Sum)
+        };
+        let spec = QuerySpec {
+            rows: vec![Dimension::new("Region".to_string())],
+            columns: vec![measure],
+            limit: Some(Limit {
+                direction: LimitDirection::Top,
+                count: 2,
+                by: SortField::Measure {
+                    column: "Amount".to_string(),
+                    aggregation: Aggregation::Sum,
+                },
+                other_bucket: true,
+            }),
+            ..QuerySpec::empty()
+        };
+        let result = run_query_with_threads(&sheet, &spec, &|_| true, 1).expect("not cancelled");
+        let shares = result.rows.iter().map(|row| row[1].as_f64()).collect::<Vec<_>>();
+        assert_eq!(result.rows.len(), 3);
+        assert!((shares[0].unwrap() - 200.0 / 3.0).abs() < 1e-9);
+        assert!((shares[1].unwrap() - 100.0 / 3.0).abs() < 1e-9);
+        assert_eq!(shares[2], None);
+    }
+
+    #[test]
+    fn other_row_takes_first_and_last_in_row_order() {
+        let sheet = sheet(vec![
+            Column::new("Region".to_string(), DataType::String, texts(&["A", "C", "B", "C", "B"])),
+            Column::new("Customer".to_string(), DataType::String, texts(&["a1", "c1", "b1", "c2", "b2"])),
+            Column::new("Amount".to_string(), DataType::Number, numbers(&[100.0, 1.0, 2.0, 1.0, 2.0])),
+        ]);
+        let spec = QuerySpec {
+            rows: vec![Dimension::new("Region".to_string())],
+            columns: vec![
+                Measure::new("Customer".to_string(), Aggregation::First),
+                Measure::new("Customer".to_string(), Aggregation::Last),
+                Measure::new("Amount".to_string(), Aggregation::Sum),
+            ],
+            limit: Some(Limit {
+                direction: LimitDirection::Top,
+                count: 1,
+                by: SortField::Measure {
+                    column: "Amount".to_string(),
+                    aggregation: Aggregation::Sum,
+                },
+                other_bucket: true,
+            }),
+            ..QuerySpec::empty()
+        };
+        let result = run_query_with_threads(&sheet, &spec, &|_| true, 1).expect("not cancelled");
+        // B outranks C, but C's rows come first.
+        let other = texts(&["Other", "c1", "b2"]);
+        assert_eq!(result.rows[1][..3], other[..]);
+        assert_eq!(result.rows[1][3], DataValue::Number(6.0));
+    }
+
+    #[test]
+    fn durations_aggregate_and_bin_as_seconds() {
+        let seconds = [90.0, 30.0, 3_600.0];
+        let sheet = sheet(vec![
+            Column::new("Task".to_string(), DataType::String, texts(&["a", "a", "b"])),
+            Column::new("Time".to_string(), DataType::Duration, seconds.map(DataValue::Duration).to_vec()),
+        ]);
+        let measure = |aggregation| Measure::new("Time".to_string(), aggregation);
+        let spec = QuerySpec {
+            rows: vec![Dimension::new("Task".to_string())],
+            columns: vec![
+                measure(Aggregation::Sum),
+                measure(Aggregation::Max),
+                measure(Aggregation::Count),
+            ],
+            ..QuerySpec::empty()
+        };
+        let result = run_query_with_threads(&sheet, &spec, &|_| true, 1).expect("not cancelled");
+        let types = result.fields.iter().map(|field| field.data_type.clone()).collect::<Vec<_>>();
+        assert_eq!(types, [DataType::String, DataType::Duration, DataType::Duration, DataType::Number]);
+        assert_eq!(
+            result.rows[0],
+            [
+                DataValue::String("a".to_string()),
+                DataValue::Duration(120.0),
+                DataValue::Duration(90.0),
+                DataValue::Number(2.0),
+            ]
+        );
+
+        let spec = QuerySpec {
+            rows: vec![Dimension {
+                bins: Some(NumericBins::Width(60.0)),
+                ..Dimension::new("Time".to_string())
+            }],
+            columns: vec![measure(Aggregation::Count)],
+            ..QuerySpec::empty()
+        };
+        let result = run_query_with_threads(&sheet, &spec, &|_| true, 1).expect("not cancelled");
+        let lower_edges = result.rows.iter().map(|row| row[0].clone()).collect::<Vec<_>>();
+        assert_eq!(lower_edges, numbers(&[0.0, 60.0, 3_600.0]));
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_result() {
+        let rows = 3 * PARTITION_ROWS + 123;
+        let region = (0..rows)
+            .map(|row| DataValue::String(["North", "South", "East", "West"][row * 7 % 4].to_string()))
+            .collect();
+        let customer = (0..rows)
+            .map(|row| match row % 97 {
+                0 => DataValue::Empty,
+                _ => DataValue::String(format!("Customer {}", row * 31 % 5_000)),
+            })
+            .collect();
+        let amount = (0..rows)
+            .map(|row| DataValue::Number((row * 7919 % 10_007) as f64 / 100.0))
+            .collect();
+        let sheet = sheet(vec![
+            Column::new("Region".to_string(), DataType::String, region),
+            Column::new("Customer".to_string(), DataType::String, customer),
+            Column::new("Amount".to_string(), DataType::Number, amount),
+        ]);
+        let measure = |column: &str, aggregation| Measure::new(column.to_string(), aggregation);
+        let spec = QuerySpec {
+            rows: vec![Dimension::new("Region".to_string())],
+            columns: vec![
+                measure("Amount", Aggregation::Sum),
+                measure("Amount", Aggregation::StdDev),
+                measure("Amount", Aggregation::Median),
+                measure("Customer", Aggregation::CountDistinct),
+                measure("Customer", Aggregation::First),
+                measure("Customer", Aggregation::Last),
+                measure("Customer", Aggregation::Mode),
+            ],
+            ..QuerySpec::empty()
+        };
+        let serial = run_query_with_threads(&sheet, &spec, &|_| true, 1).expect("not cancelled");
+        let parallel = run_query_with_threads(&sheet, &spec, &|_| true, 4).expect("not cancelled");
+        assert_eq!(serial.rows.len(), 4);
+        assert_eq!(serial.rows, parallel.rows);
+    }
+
+    #[test]
+    fn unresolvable_bins_fail_the_query() {
+        let sheet = sheet(vec![
+            Column::new("Region".to_string(), DataType::String, texts(&["A", "B"])),
+            Column::new("Amount".to_string(), DataType::Number, numbers(&[5.0, 5.0])),
+        ]);
+        let binned = |column: &str, bins| QuerySpec {
+            rows: vec![Dimension {
+                bins: Some(bins),
+                ..Dimension::new(column.to_string())
+            }],
+            columns: vec![Measure::new("Amount".to_string(), Aggregation::Count)],
+            ..QuerySpec::empty()
+        };
+        let run = |spec: &QuerySpec| {
+            run_query_with_threads(&sheet, spec, &|_| true, 1).expect("not cancelled")
+        };
+
+        let result = run(&binned("Region", NumericBins::Width(10.0)));
+        assert_eq!(result.error.as_deref(), Some("Region holds no numbers to bin"));
+        assert!(result.rows.is_empty());
+        let result = run(&binned("Amount", NumericBins::Count(4)));
+        let expected = "Binning Amount needs at least two distinct bin edges";
+        assert_eq!(result.error.as_deref(), Some(expected));
+
+        let result = run(&binned("Amount", NumericBins::Width(10.0)));
+        assert_eq!(result.error, None);
+        assert_eq!(result.rows, vec![vec![DataValue::Number(0.0), DataValue::Number(2.0)]]);
+    }
+
+    #[test]
+    fn filters_on_missing_fields_fail_the_query() {
+        let sheet = sheet(vec![Column::new("Region".to_string(), DataType::String, texts(&["A", "B"]))]);
+        let spec = QuerySpec {
+            filters: vec![Filter {
+                column: "Country".to_string(),
+                predicate: Predicate::NotEmpty,
+            }],
+            ..QuerySpec::empty()
+        };
+        let result = run_query_with_threads(&sheet, &spec, &|_| true, 1).expect("not cancelled");
+        assert_eq!(result.error.as_deref(), Some("The filter on Country refers to a missing field"));
+        assert!(result.rows.is_empty());
+    }
+
+    #[test]
+    fn shelf_fields_missing_from_the_sheet_fail_the_query() {
+        let sheet = sheet(vec![
+            Column::new("Region".to_string(), DataType::String, texts(&["A", "B"])),
+            Column::new("Amount".to_string(), DataType::Number, numbers(&[1.0, 2.0])),
+        ]);
+        let spec = |row: &str, column: &str| QuerySpec {
+            rows: vec![Dimension::new(row.to_string())],
+            columns: vec![Measure::new(column.to_string(), Aggregation::Sum)],
+            ..QuerySpec::empty()
+        };
+        let run = |spec: &QuerySpec| {
+            run_query_with_threads(&sheet, spec, &|_| true, 1).expect("not cancelled")
+        };
+
+        let result = run(&spec("Country", "Amount"));
+        assert_eq!(result.error.as_deref(), Some("Field Country not found"));
+        assert!(result.rows.is_empty());
+        let result = run(&spec("Region", "Revenue"));
+        assert_eq!(result.error.as_deref(), Some("Field Revenue not found"));
+        assert_eq!(run(&spec("Region", "Amount")).rows.len(), 2);
+    }
 }
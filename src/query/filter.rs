@@ -0,0 +1,217 @@
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+
+use crate::core::data::DataValue;
+use crate::query::model::Predicate;
+
+/// A predicate prepared for evaluation against many cells.
+///
+/// Regexes are compiled and relative date windows resolved once up front so
+/// the per-row check stays cheap.
+pub enum FilterMatcher {
+    Predicate(Predicate),
+    Regex(Regex),
+    DateWindow { start: NaiveDate, end: NaiveDate },
+    Invalid,
+}
+
+impl FilterMatcher {
+    pub fn new(predicate: &Predicate) -> Self {
+        Self::relative_to(predicate, Local::now().date_naive())
+    }
+
+    /// Prepares `predicate` with relative date windows ending on `today`.
+    fn relative_to(predicate: &Predicate, today: NaiveDate) -> Self {
+        match predicate {
+            Predicate::Matches(pattern) => match Regex::new(pattern) {
+                Ok(regex) => FilterMatcher::Regex(regex),
+                Err(_) => FilterMatcher::Invalid,
+            },
+            Predicate::LastDays(days) => FilterMatcher::DateWindow {
+                start: today - Duration::days(i64::from(*days)),
+                end: today,
+            },
+            other => FilterMatcher::Predicate(other.clone()),
+        }
+    }
+
+    pub fn matches(&self, value: &DataValue) -> bool {
+        match self {
+            FilterMatcher::Predicate(predicate) => predicate_matches(predicate, value),
+            FilterMatcher::Regex(regex) => {
+                !matches!(value, DataValue::Empty) && regex.is_match(&value.display_string())
+            }
+            FilterMatcher::DateWindow { start, end } => match value.as_date() {
+                Some(date) => date >= *start && date <= *end,
+                None => false,
+            },
+            FilterMatcher::Invalid => false,
+        }
+    }
+}
+
+/// Returns a human readable problem with the predicate, if it cannot be evaluated.
+pub fn validate(predicate: &Predicate) -> Option<String> {
+    match predicate {
+        Predicate::Matches(pattern) => Regex::new(pattern).err().map(|error| error.to_string()),
+        _ => None,
+    }
+}
+
+fn predicate_matches(predicate: &Predicate, value: &DataValue) -> bool {
+    match predicate {
+        Predicate::Equals(expected) => value_equals(value, expected),
+        Predicate::In(options) => options.iter().any(|option| value_equals(value, option)),
+        Predicate::NotIn(options) => !options.iter().any(|option| value_equals(value, option)),
+        Predicate::Contains(needle) => match value {
+            DataValue::Empty => false,
+            _ => value
+                .display_string()
+                .to_lowercase()
+                .contains(&needle.to_lowercase()),
+        },
+        Predicate::Between { min, max } => match value.as_f64() {
+            Some(number) => {
+                min.is_none_or(|min| number >= min) && max.is_none_or(|max| number <= max)
+            }
+            None => false,
+        },
+        Predicate::DateBetween { start, end } => match value.as_date() {
+            Some(date) => {
+                start.is_none_or(|start| date >= start) && end.is_none_or(|end| date <= end)
+            }
+            None => false,
+        },
+        Predicate::IsTrue => matches!(value, DataValue::Boolean(true)),
+        Predicate::IsFalse => matches!(value, DataValue::Boolean(false)),
+        Predicate::IsEmpty => value.is_null(),
+        Predicate::NotEmpty => !value.is_null(),
+        Predicate::Matches(_) | Predicate::LastDays(_) => {
+            FilterMatcher::new(predicate).matches(value)
+        }
+    }
+}
+
+/// Compares a cell with user-entered text using the cell's own type, so
+/// `"5"` matches `5.0`, `"TRUE"` matches `true` and a bare date matches every
+/// timestamp on that day.
+fn value_equals(value: &DataValue, expected: &str) -> bool {
+    let expected = expected.trim();
+    match value {
+        DataValue::Number(number) => expected.parse::<f64>().is_ok_and(|parsed| parsed == *number),
+        DataValue::Date(date) => NaiveDate::parse_from_str(expected, "%Y-%m-%d")
+            .is_ok_and(|parsed| parsed == *date),
+        DataValue::DateTime(datetime) => match NaiveDate::parse_from_str(expected, "%Y-%m-%d") {
+            Ok(date) => date == datetime.date(),
+            Err(_) => ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(expected, format).ok())
+                .is_some_and(|parsed| parsed == *datetime),
+        },
+        DataValue::Time(time) => ["%H:%M:%S", "%H:%M"]
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(expected, format).ok())
+            .is_some_and(|parsed| parsed == *time),
+        DataValue::Duration(_) => value.display_string() == expected,
+        DataValue::Boolean(flag) => expected
+            .parse::<bool>()
+            .or_else(|_| expected.to_lowercase().parse::<bool>())
+            .is_ok_and(|parsed| parsed == *flag),
+        DataValue::String(text) => text.trim() == expected,
+        DataValue::Error(error) => error.eq_ignore_ascii_case(expected),
+        DataValue::Empty => expected.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).expect("valid date")
+    }
+
+    fn text(value: &str) -> DataValue {
+        DataValue::String(value.to_string())
+    }
+
+    fn matches(predicate: Predicate, value: &DataValue) -> bool {
+        FilterMatcher::relative_to(&predicate, day(2024, 3, 10)).matches(value)
+    }
+
+    #[test]
+    fn equality_reads_the_text_as_the_cell_type() {
+        let equals = |expected: &str| Predicate::Equals(expected.to_string());
+        assert!(matches(equals("5"), &DataValue::Number(5.0)));
+        assert!(matches(equals(" 5.0 "), &DataValue::Number(5.0)));
+        assert!(!matches(equals("5"), &text("5.0")));
+        assert!(matches(equals("TRUE"), &DataValue::Boolean(true)));
+        assert!(!matches(equals("yes"), &DataValue::Boolean(true)));
+        let evening = day(2024, 3, 1).and_hms_opt(18, 30, 0).expect("valid time");
+        assert!(matches(equals("2024-03-01"), &DataValue::DateTime(evening)));
+        assert!(matches(equals("2024-03-01 18:30"), &DataValue::DateTime(evening)));
+        assert!(!matches(equals("2024-03-01 18:31"), &DataValue::DateTime(evening)));
+        assert!(matches(equals("#n/a"), &DataValue::Error("#N/A".to_string())));
+        assert!(matches(equals(""), &DataValue::Empty));
+    }
+
+    #[test]
+    fn in_and_not_in_compare_each_option() {
+        let options = vec!["North".to_string(), "3".to_string()];
+        for value in [text("North"), DataValue::Number(3.0)] {
+            assert!(matches(Predicate::In(options.clone()), &value));
+            assert!(!matches(Predicate::NotIn(options.clone()), &value));
+        }
+        assert!(!matches(Predicate::In(options.clone()), &text("South")));
+        assert!(matches(Predicate::NotIn(options), &text("South")));
+    }
+
+    #[test]
+    fn ranges_include_their_bounds_and_may_be_open() {
+        let between = |min, max| Predicate::Between { min, max };
+        for (value, expected) in [(1.0, true), (5.0, true), (0.999, false), (5.001, false)] {
+            assert_eq!(matches(between(Some(1.0), Some(5.0)), &DataValue::Number(value)), expected);
+        }
+        assert!(matches(between(None, Some(5.0)), &DataValue::Number(-1e9)));
+        assert!(matches(between(Some(1.0), None), &DataValue::Number(1e9)));
+        assert!(!matches(between(None, None), &text("5")));
+
+        let dates = |start, end| Predicate::DateBetween { start, end };
+        let march = dates(Some(day(2024, 3, 1)), Some(day(2024, 3, 31)));
+        assert!(matches(march.clone(), &DataValue::Date(day(2024, 3, 1))));
+        let last_evening = day(2024, 3, 31).and_hms_opt(23, 59, 0).expect("valid time");
+        assert!(matches(march.clone(), &DataValue::DateTime(last_evening)));
+        assert!(!matches(march, &DataValue::Date(day(2024, 4, 1))));
+        assert!(matches(dates(None, Some(day(2024, 3, 1))), &DataValue::Date(day(1999, 1, 1))));
+    }
+
+    #[test]
+    fn last_days_ends_on_the_given_day() {
+        let last_week = || Predicate::LastDays(7);
+        assert!(matches(last_week(), &DataValue::Date(day(2024, 3, 10))));
+        assert!(matches(last_week(), &DataValue::Date(day(2024, 3, 3))));
+        assert!(!matches(last_week(), &DataValue::Date(day(2024, 3, 2))));
+        assert!(!matches(last_week(), &DataValue::Date(day(2024, 3, 11))));
+        assert!(!matches(last_week(), &text("2024-03-05")));
+    }
+
+    #[test]
+    fn invalid_regexes_match_nothing_and_are_reported() {
+        let invalid = Predicate::Matches("(unclosed".to_string());
+        assert!(validate(&invalid).is_some());
+        assert!(!matches(invalid, &text("(unclosed")));
+        let valid = Predicate::Matches("^No".to_string());
+        assert_eq!(validate(&valid), None);
+        assert!(matches(valid.clone(), &text("North")));
+        assert!(!matches(valid, &DataValue::Empty));
+    }
+
+    #[test]
+    fn cell_errors_count_as_empty() {
+        let error = DataValue::Error("#DIV/0!".to_string());
+        assert!(matches(Predicate::IsEmpty, &error));
+        assert!(!matches(Predicate::NotEmpty, &error));
+        assert!(matches(Predicate::IsEmpty, &DataValue::Empty));
+        assert!(!matches(Predicate::IsEmpty, &DataValue::Number(0.0)));
+    }
+}
@@ -1,2 +1,8 @@
+pub mod aggregate;
+pub mod bins;
+pub mod cache;
 pub mod engine;
+pub mod filter;
 pub mod model;
+pub mod sketch;
+pub mod table_calc;
@@ -1,26 +1,597 @@
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::calc::field::CalculatedField;
+use crate::core::data::DataType;
+use crate::core::dates::DatePart;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum Aggregation {
     Sum,
     Avg,
     Count,
     Min,
     Max,
+    CountDistinct,
+    Median,
+    Percentile(u8),
+    StdDev,
+    StdDevPop,
+    Variance,
+    VariancePop,
+    First,
+    Last,
+    Mode,
+}
+
+impl Aggregation {
+    /// Aggregations offered in the UI, including the common percentiles.
+    pub const COMMON: [Aggregation; 17] = [
+        Aggregation::Sum,
+        Aggregation::Avg,
+        Aggregation::Count,
+        Aggregation::CountDistinct,
+        Aggregation::Min,
+        Aggregation::Max,
+        Aggregation::Median,
+        Aggregation::Percentile(90),
+        Aggregation::Percentile(95),
+        Aggregation::Percentile(99),
+        Aggregation::StdDev,
+        Aggregation::StdDevPop,
+        Aggregation::Variance,
+        Aggregation::VariancePop,
+        Aggregation::First,
+        Aggregation::Last,
+        Aggregation::Mode,
+    ];
+
+    pub fn label(self) -> String {
+        match self {
+            Aggregation::Sum => "Sum".to_string(),
+            Aggregation::Avg => "Avg".to_string(),
+            Aggregation::Count => "Count".to_string(),
+            Aggregation::Min => "Min".to_string(),
+            Aggregation::Max => "Max".to_string(),
+            Aggregation::CountDistinct => "Count Distinct".to_string(),
+            Aggregation::Median => "Median".to_string(),
+            Aggregation::Percentile(p) => format!("P{p}"),
+            Aggregation::StdDev => "StdDev".to_string(),
+            Aggregation::StdDevPop => "StdDev (pop)".to_string(),
+            Aggregation::Variance => "Variance".to_string(),
+            Aggregation::VariancePop => "Variance (pop)".to_string(),
+            Aggregation::First => "First".to_string(),
+            Aggregation::Last => "Last".to_string(),
+            Aggregation::Mode => "Mode".to_string(),
+        }
+    }
+
+    /// Compact name used in measure headers, e.g. `SUM` in `SUM(Sales)`.
+    pub fn short_label(self) -> String {
+        match self {
+            Aggregation::Sum => "SUM".to_string(),
+            Aggregation::Avg => "AVG".to_string(),
+            Aggregation::Count => "CNT".to_string(),
+            Aggregation::Min => "MIN".to_string(),
+            Aggregation::Max => "MAX".to_string(),
+            Aggregation::CountDistinct => "COUNTD".to_string(),
+            Aggregation::Median => "MEDIAN".to_string(),
+            Aggregation::Percentile(p) => format!("P{p}"),
+            Aggregation::StdDev => "STDEV".to_string(),
+            Aggregation::StdDevPop => "STDEVP".to_string(),
+            Aggregation::Variance => "VAR".to_string(),
+            Aggregation::VariancePop => "VARP".to_string(),
+            Aggregation::First => "FIRST".to_string(),
+            Aggregation::Last => "LAST".to_string(),
+            Aggregation::Mode => "MODE".to_string(),
+        }
+    }
+
+    /// Default aggregation when a column of the given type is dropped on
+    /// the Columns shelf.
+    pub fn default_for(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Number | DataType::Duration => Aggregation::Sum,
+            _ => Aggregation::CountDistinct,
+        }
+    }
+
+    /// Whether the result keeps the type of the source column instead of
+    /// being a number.
+    pub fn preserves_type(self) -> bool {
+        matches!(self, Aggregation::First | Aggregation::Last | Aggregation::Mode)
+    }
+
+    /// Whether the result over a Duration column is itself a duration, such
+    /// as a total or the longest time, rather than a count.
+    pub fn keeps_duration(self) -> bool {
+        matches!(
+            self,
+            Aggregation::Sum
+                | Aggregation::Avg
+                | Aggregation::Min
+                | Aggregation::Max
+                | Aggregation::Median
+                | Aggregation::Percentile(_)
+                | Aggregation::StdDev
+                | Aggregation::StdDevPop
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Predicate {
+    Equals(String),
+    In(Vec<String>),
+    NotIn(Vec<String>),
+    Contains(String),
+    Matches(String),
+    Between { min: Option<f64>, max: Option<f64> },
+    DateBetween {
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+    },
+    LastDays(u32),
+    IsTrue,
+    IsFalse,
+    IsEmpty,
+    NotEmpty,
+}
+
+impl Predicate {
+    /// One template per predicate kind, in the order the filter shelf lists them.
+    pub fn templates() -> [Predicate; 12] {
+        [
+            Predicate::Equals(String::new()),
+            Predicate::In(Vec::new()),
+            Predicate::NotIn(Vec::new()),
+            Predicate::Contains(String::new()),
+            Predicate::Matches(String::new()),
+            Predicate::Between {
+                min: None,
+                max: None,
+            },
+            Predicate::DateBetween {
+                start: None,
+                end: None,
+            },
+            Predicate::LastDays(90),
+            Predicate::IsTrue,
+            Predicate::IsFalse,
+            Predicate::IsEmpty,
+            Predicate::NotEmpty,
+        ]
+    }
+
+    /// Picks a sensible starting predicate for a column of the given type.
+    pub fn default_for(data_type: &DataType, sample: String) -> Self {
+        match data_type {
+            DataType::Number => Predicate::Between {
+                min: None,
+                max: None,
+            },
+            DataType::Date | DataType::DateTime => Predicate::LastDays(90),
+            DataType::Boolean => Predicate::IsTrue,
+            DataType::String | DataType::Time | DataType::Duration | DataType::Empty => {
+                Predicate::Equals(sample)
+            }
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Predicate::Equals(_) => "equals",
+            Predicate::In(_) => "in",
+            Predicate::NotIn(_) => "not in",
+            Predicate::Contains(_) => "contains",
+            Predicate::Matches(_) => "matches regex",
+            Predicate::Between { .. } => "between",
+            Predicate::DateBetween { .. } => "date between",
+            Predicate::LastDays(_) => "last N days",
+            Predicate::IsTrue => "is true",
+            Predicate::IsFalse => "is false",
+            Predicate::IsEmpty => "is empty",
+            Predicate::NotEmpty => "is not empty",
+        }
+    }
+
+    pub fn same_kind(&self, other: &Predicate) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "FilterRepr")]
 pub struct Filter {
     pub column: String,
-    pub equals: String,
+    pub predicate: Predicate,
+}
+
+/// Accepts both the current layout and the equality-only layout written by
+/// earlier `.viz` files.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FilterRepr {
+    Current { column: String, predicate: Predicate },
+    Legacy { column: String, equals: String },
+}
+
+impl From<FilterRepr> for Filter {
+    fn from(repr: FilterRepr) -> Self {
+        match repr {
+            FilterRepr::Current { column, predicate } => Filter { column, predicate },
+            FilterRepr::Legacy { column, equals } => Filter {
+                column,
+                predicate: Predicate::Equals(equals),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+impl SortDirection {
+    pub fn toggled(self) -> Self {
+        match self {
+            SortDirection::Ascending => SortDirection::Descending,
+            SortDirection::Descending => SortDirection::Ascending,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortDirection::Ascending => "Asc",
+            SortDirection::Descending => "Desc",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SortField {
+    /// A Rows shelf dimension, by its label.
+    Dimension(String),
+    Measure {
+        column: String,
+        aggregation: Aggregation,
+    },
+    /// An aggregate calculation on the Columns shelf, by name.
+    Calculation(String),
+}
+
+impl SortField {
+    pub fn label(&self) -> String {
+        match self {
+            SortField::Dimension(column) => column.clone(),
+            SortField::Measure {
+                column,
+                aggregation,
+            } => measure_label(column, *aggregation),
+            SortField::Calculation(name) => name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SortOrder {
+    /// Type-aware ordering of the field's values.
+    Natural,
+    /// Listed display values first, in the given order, then the rest naturally.
+    Manual(Vec<String>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SortKey {
+    pub field: SortField,
+    pub direction: SortDirection,
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum LimitDirection {
+    Top,
+    Bottom,
+}
+
+impl LimitDirection {
+    pub const ALL: [LimitDirection; 2] = [LimitDirection::Top, LimitDirection::Bottom];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LimitDirection::Top => "Top",
+            LimitDirection::Bottom => "Bottom",
+        }
+    }
+}
+
+/// Keeps the `count` highest (or lowest) groups ranked by `by`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Limit {
+    pub direction: LimitDirection,
+    pub count: usize,
+    pub by: SortField,
+    /// Rolls the groups that were cut into a single trailing "Other" row.
+    pub other_bucket: bool,
+}
+
+/// How a Date dimension's values are grouped.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DateGrouping {
+    /// The numeric part, e.g. month 3 of every year together.
+    Part(DatePart),
+    /// The start of each period, e.g. 2024-03-01 for March 2024.
+    Truncate(DatePart),
+}
+
+impl DateGrouping {
+    /// Groupings offered in the UI: every part, then every truncation.
+    /// Hours and minutes are only offered for timestamps.
+    pub fn options(with_time: bool) -> Vec<DateGrouping> {
+        let parts = DatePart::ALL
+            .into_iter()
+            .filter(move |part| with_time || !part.is_time_of_day());
+        parts
+            .clone()
+            .map(DateGrouping::Part)
+            .chain(
+                parts
+                    .filter(|part| part.is_period())
+                    .map(DateGrouping::Truncate),
+            )
+            .collect()
+    }
+
+    fn part(self) -> DatePart {
+        match self {
+            DateGrouping::Part(part) | DateGrouping::Truncate(part) => part,
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            DateGrouping::Part(part) => part.label().to_string(),
+            DateGrouping::Truncate(part) => format!("{} start", part.label()),
+        }
+    }
+
+    /// The same kind of grouping one level down the date hierarchy; plain
+    /// dates stop at days.
+    pub fn drill_down(self, with_time: bool) -> Option<DateGrouping> {
+        let next = self.drill_down_any()?;
+        (with_time || !next.part().is_time_of_day()).then_some(next)
+    }
+
+    fn drill_down_any(self) -> Option<DateGrouping> {
+        match self {
+            DateGrouping::Part(part) => part.drill_down().map(DateGrouping::Part),
+            DateGrouping::Truncate(part) => part.drill_down().map(DateGrouping::Truncate),
+        }
+    }
+}
+
+/// How a Number dimension's values are split into bins. Each value is
+/// grouped under the lower edge of its bin.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum NumericBins {
+    /// Bins of this width, aligned to zero.
+    Width(f64),
+    /// This many equal-width bins between the column's minimum and maximum.
+    Count(u32),
+    /// This many bins holding roughly the same number of values.
+    Quantile(u32),
+    /// Ascending edges; values outside the first and last edge are left
+    /// empty.
+    Edges(Vec<f64>),
+}
+
+impl NumericBins {
+    pub fn templates() -> Vec<NumericBins> {
+        vec![
+            NumericBins::Width(10.0),
+            NumericBins::Count(10),
+            NumericBins::Quantile(4),
+            NumericBins::Edges(Vec::new()),
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NumericBins::Width(_) => "Fixed width",
+            NumericBins::Count(_) => "Bin count",
+            NumericBins::Quantile(_) => "Quantiles",
+            NumericBins::Edges(_) => "Custom edges",
+        }
+    }
+
+    pub fn same_kind(&self, other: &NumericBins) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// A Rows shelf entry: a source column and, for dates and numbers, how its
+/// values are grouped.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Dimension {
+    pub column: String,
+    /// `None` groups by the exact value.
+    #[serde(default)]
+    pub date: Option<DateGrouping>,
+    #[serde(default)]
+    pub bins: Option<NumericBins>,
+}
+
+impl Dimension {
+    pub fn new(column: String) -> Self {
+        Self {
+            column,
+            date: None,
+            bins: None,
+        }
+    }
+
+    /// Dates start at the top of the Year → Quarter → Month → Day hierarchy.
+    pub fn default_for(column: String, data_type: &DataType) -> Self {
+        let date = match data_type {
+            DataType::Date | DataType::DateTime => Some(DateGrouping::Part(DatePart::Year)),
+            _ => None,
+        };
+        Self {
+            date,
+            ..Self::new(column)
+        }
+    }
+
+    /// Result field name, also used by sort and limit fields.
+    pub fn label(&self) -> String {
+        if self.bins.is_some() {
+            return format!("{} (bin)", self.column);
+        }
+        match self.date {
+            None => self.column.clone(),
+            Some(DateGrouping::Part(part)) => {
+                format!("{}({})", part.label().to_uppercase(), self.column)
+            }
+            Some(grouping) => format!("{} ({})", self.column, grouping.label().to_lowercase()),
+        }
+    }
+
+    pub fn sort_field(&self) -> SortField {
+        SortField::Dimension(self.label())
+    }
+}
+
+/// A post-aggregation calculation over a measure's values in result order.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum TableCalcKind {
+    RunningSum,
+    /// Share of the total, as a percentage.
+    PercentOfTotal,
+    /// Change from the previous row.
+    Difference,
+    /// Change from the previous row, as a percentage of the previous value.
+    PercentDifference,
+    /// Highest value first; ties share a rank and leave gaps (1, 1, 3).
+    Rank,
+    /// Highest value first; ties share a rank without gaps (1, 1, 2).
+    DenseRank,
+    /// Average of the current row and the rows before it, over a window of
+    /// this many rows.
+    MovingAverage(u32),
+}
+
+impl TableCalcKind {
+    pub const ALL: [TableCalcKind; 7] = [
+        TableCalcKind::RunningSum,
+        TableCalcKind::PercentOfTotal,
+        TableCalcKind::Difference,
+        TableCalcKind::PercentDifference,
+        TableCalcKind::Rank,
+        TableCalcKind::DenseRank,
+        TableCalcKind::MovingAverage(3),
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TableCalcKind::RunningSum => "Running sum",
+            TableCalcKind::PercentOfTotal => "Percent of total",
+            TableCalcKind::Difference => "Difference",
+            TableCalcKind::PercentDifference => "Percent difference",
+            TableCalcKind::Rank => "Rank",
+            TableCalcKind::DenseRank => "Dense rank",
+            TableCalcKind::MovingAverage(_) => "Moving average",
+        }
+    }
+
+    pub fn same_kind(self, other: TableCalcKind) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+}
+
+/// Which rows a table calculation runs over.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum TableCalcScope {
+    /// Down the whole result.
+    Table,
+    /// Down each partition of rows sharing every dimension but the last,
+    /// e.g. months within each year.
+    Partition,
+}
+
+impl TableCalcScope {
+    pub const ALL: [TableCalcScope; 2] = [TableCalcScope::Table, TableCalcScope::Partition];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TableCalcScope::Table => "Table (down)",
+            TableCalcScope::Partition => "Per partition",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct TableCalc {
+    pub kind: TableCalcKind,
+    pub scope: TableCalcScope,
+}
+
+/// A Columns shelf entry: a source column, how it is aggregated and an
+/// optional table calculation over the aggregated values.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Measure {
+    pub column: String,
+    pub aggregation: Aggregation,
+    #[serde(default)]
+    pub table_calc: Option<TableCalc>,
+}
+
+impl Measure {
+    pub fn new(column: String, aggregation: Aggregation) -> Self {
+        Self {
+            column,
+            aggregation,
+            table_calc: None,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let label = measure_label(&self.column, self.aggregation);
+        match self.table_calc {
+            Some(table_calc) => format!("{} of {label}", table_calc.kind.label()),
+            None => label,
+        }
+    }
+
+    pub fn sort_field(&self) -> SortField {
+        SortField::Measure {
+            column: self.column.clone(),
+            aggregation: self.aggregation,
+        }
+    }
+}
+
+fn measure_label(column: &str, aggregation: Aggregation) -> String {
+    format!("{}({column})", aggregation.short_label())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "QuerySpecRepr")]
 pub struct QuerySpec {
-    pub rows: Vec<String>,
-    pub columns: Vec<String>,
-    pub aggregations: Vec<Aggregation>,
+    pub rows: Vec<Dimension>,
+    pub columns: Vec<Measure>,
     pub filters: Vec<Filter>,
+    /// Applied in order; groups are always ordered by their dimensions
+    /// afterwards so results are stable between runs.
+    #[serde(default)]
+    pub sort: Vec<SortKey>,
+    #[serde(default)]
+    pub limit: Option<Limit>,
+    /// Formulas over aggregates, e.g. `SUM([Profit]) / SUM([Sales])`, shown
+    /// after the measures and evaluated once per group.
+    #[serde(default)]
+    pub calculations: Vec<CalculatedField>,
 }
 
 impl QuerySpec {
@@ -28,8 +599,241 @@ impl QuerySpec {
         Self {
             rows: Vec::new(),
             columns: Vec::new(),
-            aggregations: vec![Aggregation::Sum],
             filters: Vec::new(),
+            sort: Vec::new(),
+            limit: None,
+            calculations: Vec::new(),
+        }
+    }
+
+    /// Adds a sort on `field`, or flips its direction if it is already sorted.
+    pub fn toggle_sort(&mut self, field: SortField) {
+        match self.sort.iter_mut().find(|key| key.field == field) {
+            Some(key) => key.direction = key.direction.toggled(),
+            None => self.sort.push(SortKey {
+                field,
+                direction: SortDirection::Ascending,
+                order: SortOrder::Natural,
+            }),
         }
     }
+
+    /// The sort field of every dimension, measure and aggregate calculation
+    /// on the shelves.
+    pub fn shelf_fields(&self) -> Vec<SortField> {
+        self.rows
+            .iter()
+            .map(Dimension::sort_field)
+            .chain(self.columns.iter().map(Measure::sort_field))
+            .chain(self.calculations.iter().map(|field| SortField::Calculation(field.name.clone())))
+            .collect()
+    }
+
+    /// Follows a pill whose grouping or aggregation changed from `old` to
+    /// `new`: sort keys and the limit on `old` move to `new`; when the pill
+    /// was removed the keys are dropped and the limit cleared. Nothing
+    /// changes while another pill still gives `old`.
+    pub fn replace_field(&mut self, old: &SortField, new: Option<&SortField>) {
+        if self.shelf_fields().contains(old) {
+            return;
+        }
+        match (&mut self.limit, new) {
+            (Some(limit), Some(new)) if &limit.by == old => limit.by = new.clone(),
+            (Some(limit), None) if &limit.by == old => self.limit = None,
+            _ => {}
+        }
+        let already_sorted = new.is_some_and(|new| self.sort.iter().any(|key| &key.field == new));
+        match new {
+            Some(new) if !already_sorted => {
+                for key in self.sort.iter_mut().filter(|key| &key.field == old) {
+                    key.field = new.clone();
+                }
+            }
+            _ => self.sort.retain(|key| &key.field != old),
+        }
+    }
+}
+
+/// Accepts both per-measure aggregations and the earlier layout, where
+/// `columns` held plain column names and one `aggregations` list applied to
+/// every one of them.
+#[derive(Deserialize)]
+struct QuerySpecRepr {
+    rows: Vec<DimensionRepr>,
+    columns: Vec<MeasureRepr>,
+    #[serde(default)]
+    aggregations: Vec<Aggregation>,
+    filters: Vec<Filter>,
+    #[serde(default)]
+    sort: Vec<SortKey>,
+    #[serde(default)]
+    limit: Option<Limit>,
+    #[serde(default)]
+    calculations: Vec<CalculatedField>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DimensionRepr {
+    Current(Dimension),
+    Legacy(String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MeasureRepr {
+    Current(Measure),
+    Legacy(String),
+}
+
+impl From<QuerySpecRepr> for QuerySpec {
+    fn from(repr: QuerySpecRepr) -> Self {
+        let legacy_aggregations = if repr.aggregations.is_empty() {
+            vec![Aggregation::Sum]
+        } else {
+            repr.aggregations
+        };
+        let columns = repr
+            .columns
+            .into_iter()
+            .flat_map(|measure| match measure {
+                MeasureRepr::Current(measure) => vec![measure],
+                MeasureRepr::Legacy(column) => legacy_aggregations
+                    .iter()
+                    .map(|aggregation| Measure::new(column.clone(), *aggregation))
+                    .collect(),
+            })
+            .collect();
+        let rows = repr
+            .rows
+            .into_iter()
+            .map(|dimension| match dimension {
+                DimensionRepr::Current(dimension) => dimension,
+                DimensionRepr::Legacy(column) => Dimension::new(column),
+            })
+            .collect();
+        QuerySpec {
+            rows,
+            columns,
+            filters: repr.filters,
+            sort: repr.sort,
+            limit: repr.limit,
+            calculations: repr.calculations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(fields: &[SortField]) -> Vec<SortKey> {
+        fields
+            .iter()
+            .map(|field| SortKey {
+                field: field.clone(),
+                direction: SortDirection::Descending,
+                order: SortOrder::Natural,
+            })
+            .collect()
+    }
+
+    fn sort_fields(spec: &QuerySpec) -> Vec<SortField> {
+        spec.sort.iter().map(|key| key.field.clone()).collect()
+    }
+
+    #[test]
+    fn sort_keys_follow_changed_and_removed_pills() {
+        let year = Dimension::default_for("Date".to_string(), &DataType::Date);
+        let month = Dimension {
+            date: Some(DateGrouping::Part(DatePart::Month)),
+            ..year.clone()
+        };
+        let sum = Measure::new("Sales".to_string(), Aggregation::Sum);
+        let avg = Measure::new("Sales".to_string(), Aggregation::Avg);
+        let mut spec = QuerySpec {
+            rows: vec![month.clone()],
+            columns: vec![avg.clone()],
+            sort: sorted(&[year.sort_field(), sum.sort_field()]),
+            ..QuerySpec::empty()
+        };
+        spec.replace_field(&year.sort_field(), Some(&month.sort_field()));
+        spec.replace_field(&sum.sort_field(), Some(&avg.sort_field()));
+        assert_eq!(sort_fields(&spec), vec![month.sort_field(), avg.sort_field()]);
+        assert_eq!(spec.sort[0].direction, SortDirection::Descending);
+
+        // The key stays while an identical pill is left on the shelf and
+        // goes with the last one.
+        spec.replace_field(&month.sort_field(), None);
+        assert_eq!(sort_fields(&spec), vec![month.sort_field(), avg.sort_field()]);
+        spec.rows.clear();
+        spec.replace_field(&month.sort_field(), None);
+        assert_eq!(sort_fields(&spec), vec![avg.sort_field()]);
+    }
+
+    #[test]
+    fn limit_follows_its_measure() {
+        let sum = Measure::new("Sales".to_string(), Aggregation::Sum);
+        let max = Measure::new("Sales".to_string(), Aggregation::Max);
+        let mut spec = QuerySpec {
+            columns: vec![max.clone()],
+            limit: Some(Limit {
+                direction: LimitDirection::Top,
+                count: 5,
+                by: sum.sort_field(),
+                other_bucket: true,
+            }),
+            ..QuerySpec::empty()
+        };
+        spec.replace_field(&sum.sort_field(), Some(&max.sort_field()));
+        assert_eq!(spec.limit.as_ref().map(|limit| &limit.by), Some(&max.sort_field()));
+        spec.columns.clear();
+        spec.replace_field(&max.sort_field(), None);
+        assert!(spec.limit.is_none());
+    }
+
+    #[test]
+    fn baseline_specs_expand_into_measures_and_dimensions() {
+        let json = r#"{
+            "rows": ["Region", "Category"],
+            "columns": ["Sales", "Profit"],
+            "aggregations": ["Sum", "Avg"],
+            "filters": [{"column": "Region", "equals": "North"}]
+        }"#;
+        let spec = serde_json::from_str::<QuerySpec>(json).expect("a baseline spec");
+        assert_eq!(
+            spec.rows,
+            [Dimension::new("Region".to_string()), Dimension::new("Category".to_string())]
+        );
+        let measure = |column: &str, aggregation| Measure::new(column.to_string(), aggregation);
+        assert_eq!(
+            spec.columns,
+            [
+                measure("Sales", Aggregation::Sum),
+                measure("Sales", Aggregation::Avg),
+                measure("Profit", Aggregation::Sum),
+                measure("Profit", Aggregation::Avg),
+            ]
+        );
+        assert_eq!(spec.filters[0].predicate, Predicate::Equals("North".to_string()));
+        assert!(spec.sort.is_empty() && spec.limit.is_none() && spec.calculations.is_empty());
+
+        // Without an aggregations list, columns were summed.
+        let json = r#"{"rows": [], "columns": ["Sales"], "filters": []}"#;
+        let spec = serde_json::from_str::<QuerySpec>(json).expect("a baseline spec");
+        assert_eq!(spec.columns, [measure("Sales", Aggregation::Sum)]);
+    }
+
+    #[test]
+    fn current_specs_round_trip() {
+        let spec = QuerySpec {
+            rows: vec![Dimension::default_for("Date".to_string(), &DataType::Date)],
+            columns: vec![Measure::new("Sales".to_string(), Aggregation::Percentile(90))],
+            ..QuerySpec::empty()
+        };
+        let json = serde_json::to_string(&spec).expect("serializable");
+        let restored = serde_json::from_str::<QuerySpec>(&json).expect("deserializable");
+        assert_eq!(restored.rows, spec.rows);
+        assert_eq!(restored.columns, spec.columns);
+    }
 }
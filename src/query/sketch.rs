@@ -0,0 +1,261 @@
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+
+/// Index bits of a `DistinctSketch`: 2^12 registers, about 1.6% error.
+const DISTINCT_BITS: u32 = 12;
+/// The t-digest compression: at most about this many centroids are kept.
+const COMPRESSION: f64 = 200.0;
+/// Values buffered before they are folded into the centroids.
+const BUFFER_LIMIT: usize = 1024;
+
+/// Approximate distinct count (HyperLogLog) over 64-bit hashes. Sketches
+/// merge by keeping each register's maximum.
+#[derive(Debug, Clone)]
+pub struct DistinctSketch {
+    registers: Vec<u8>,
+}
+
+impl DistinctSketch {
+    pub fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << DISTINCT_BITS],
+        }
+    }
+
+    pub fn insert_hash(&mut self, hash: u64) {
+        let index = (hash >> (64 - DISTINCT_BITS)) as usize;
+        let rank = ((hash << DISTINCT_BITS).leading_zeros()).min(64 - DISTINCT_BITS) + 1;
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    pub fn merge(&mut self, other: &DistinctSketch) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    pub fn estimate(&self) -> f64 {
+        let registers = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / registers);
+        let harmonic = self
+            .registers
+            .iter()
+            .map(|register| 2f64.powi(-i32::from(*register)))
+            .sum::<f64>();
+        let raw = alpha * registers * registers / harmonic;
+        let zeros = self.registers.iter().filter(|register| **register == 0).count();
+        // Small counts are estimated from the share of untouched registers.
+        if raw <= 2.5 * registers && zeros > 0 {
+            registers * (registers / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// Approximate quantiles (a merging t-digest). Values are summarized as
+/// weighted centroids that stay small near the minimum and maximum, so
+/// tail percentiles such as P99 remain close; sketches merge by pooling
+/// their centroids.
+#[derive(Debug, Clone)]
+pub struct QuantileSketch {
+    centroids: Vec<Centroid>,
+    buffer: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl QuantileSketch {
+    pub fn new() -> Self {
+        Self {
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(value);
+        if self.buffer.len() >= BUFFER_LIMIT {
+            self.compress();
+        }
+    }
+
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.centroids.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.compress();
+    }
+
+    /// The value at percentile `p` (0 to 100), interpolating between
+    /// centroid centers the way an exact percentile interpolates between
+    /// neighbouring values.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let mut sketch = self.clone();
+        sketch.compress();
+        let centroids = &sketch.centroids;
+        let total = centroids.iter().map(|centroid| centroid.weight).sum::<f64>();
+        if centroids.is_empty() {
+            return None;
+        }
+        let rank = p.clamp(0.0, 100.0) / 100.0 * (total - 1.0);
+        // Each centroid sits at the average rank of the values it holds.
+        let mut points = Vec::with_capacity(centroids.len() + 2);
+        points.push((0.0, sketch.min));
+        let mut before = 0.0;
+        for centroid in centroids {
+            points.push((before + (centroid.weight - 1.0) / 2.0, centroid.mean));
+            before += centroid.weight;
+        }
+        points.push((total - 1.0, sketch.max));
+        let upper = points
+            .iter()
+            .position(|(position, _)| *position >= rank)
+            .unwrap_or(points.len() - 1);
+        if upper == 0 {
+            return Some(points[0].1);
+        }
+        let (left_rank, left) = points[upper - 1];
+        let (right_rank, right) = points[upper];
+        if right_rank <= left_rank {
+            return Some(right);
+        }
+        Some(left + (right - left) * (rank - left_rank) / (right_rank - left_rank))
+    }
+
+    /// Folds the buffer into the centroids and merges neighbours while each
+    /// centroid stays within one unit of the t-digest scale function.
+    fn compress(&mut self) {
+        if self.buffer.is_empty() && self.centroids.len() <= COMPRESSION as usize {
+            return;
+        }
+        let mut pooled = std::mem::take(&mut self.centroids);
+        pooled.extend(self.buffer.drain(..).map(|mean| Centroid { mean, weight: 1.0 }));
+        pooled.sort_by(|left, right| left.mean.partial_cmp(&right.mean).unwrap_or(Ordering::Equal));
+        let total = pooled.iter().map(|centroid| centroid.weight).sum::<f64>();
+
+        let scale = |q: f64| COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).asin();
+        let inverse = |k: f64| ((2.0 * PI * k / COMPRESSION).sin() + 1.0) / 2.0;
+        let mut merged: Vec<Centroid> = Vec::with_capacity(COMPRESSION as usize);
+        let mut before = 0.0;
+        let mut limit = inverse(scale(0.0) + 1.0);
+        for centroid in pooled {
+            match merged.last_mut() {
+                Some(last) if (before + last.weight + centroid.weight) / total <= limit => {
+                    last.weight += centroid.weight;
+                    last.mean += (centroid.mean - last.mean) * centroid.weight / last.weight;
+                }
+                _ => {
+                    if let Some(last) = merged.last() {
+                        before += last.weight;
+                        limit = inverse(scale(before / total) + 1.0);
+                    }
+                    merged.push(centroid);
+                }
+            }
+        }
+        self.centroids = merged;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use super::*;
+
+    fn hash(value: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn distinct(values: impl Iterator<Item = u64>) -> DistinctSketch {
+        let mut sketch = DistinctSketch::new();
+        values.for_each(|value| sketch.insert_hash(hash(value)));
+        sketch
+    }
+
+    /// A fixed shuffle of 0..count, so sketches see values out of order.
+    fn shuffled(count: u64) -> Vec<f64> {
+        (0..count).map(|index| ((index * 7919) % count) as f64).collect()
+    }
+
+    fn quantiles(values: &[f64]) -> QuantileSketch {
+        let mut sketch = QuantileSketch::new();
+        values.iter().for_each(|value| sketch.insert(*value));
+        sketch
+    }
+
+    #[test]
+    fn distinct_estimate_stays_within_error_bounds() {
+        for count in [100, 5_000, 100_000] {
+            let estimate = distinct(0..count).estimate();
+            let error = (estimate - count as f64).abs() / count as f64;
+            // Three standard errors of 1.04 / sqrt(4096).
+            assert!(error < 0.05, "{count} values estimated as {estimate}");
+        }
+    }
+
+    #[test]
+    fn duplicates_do_not_change_the_distinct_estimate() {
+        let once = distinct(0..10_000).estimate();
+        let twice = distinct((0..10_000).chain(0..10_000)).estimate();
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn merged_distinct_sketches_equal_one_over_the_union() {
+        let mut left = distinct(0..6_000);
+        left.merge(&distinct(4_000..10_000));
+        let union = distinct(0..10_000);
+        assert_eq!(left.registers, union.registers);
+    }
+
+    #[test]
+    fn percentiles_stay_close_in_rank() {
+        let count = 100_000;
+        let sketch = quantiles(&shuffled(count));
+        for p in [1.0, 10.0, 25.0, 50.0, 75.0, 90.0, 99.0, 99.9] {
+            let expected = p / 100.0 * (count - 1) as f64;
+            let estimate = sketch.percentile(p).expect("values were added");
+            assert!(
+                (estimate - expected).abs() / (count as f64) < 0.005,
+                "P{p} estimated as {estimate}, expected {expected}"
+            );
+        }
+        assert_eq!(sketch.percentile(0.0), Some(0.0));
+        assert_eq!(sketch.percentile(100.0), Some((count - 1) as f64));
+    }
+
+    #[test]
+    fn merged_quantile_sketches_match_one_over_all_values() {
+        let values = shuffled(50_000);
+        let (first, second) = values.split_at(20_000);
+        let mut merged = quantiles(first);
+        merged.merge(&quantiles(second));
+        let whole = quantiles(&values);
+        assert!(merged.centroids.len() <= COMPRESSION as usize);
+        for p in [1.0, 50.0, 99.0] {
+            let merged = merged.percentile(p).expect("values were added");
+            let whole = whole.percentile(p).expect("values were added");
+            assert!((merged - whole).abs() / (values.len() as f64) < 0.005, "P{p}: {merged} vs {whole}");
+        }
+    }
+
+    #[test]
+    fn empty_quantile_sketch_has_no_percentile() {
+        assert_eq!(QuantileSketch::new().percentile(50.0), None);
+    }
+}
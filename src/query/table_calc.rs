@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use crate::core::data::DataValue;
+use crate::query::model::{TableCalc, TableCalcKind, TableCalcScope};
+
+/// Replaces the values in column `index` of the sorted `rows` with the
+/// table calculation computed over them. The leading `dimension_count`
+/// columns hold each row's dimension values.
+pub fn apply_table_calc(
+    rows: &mut [Vec<DataValue>],
+    index: usize,
+    dimension_count: usize,
+    table_calc: &TableCalc,
+) {
+    for partition in partitions(rows, dimension_count, table_calc.scope) {
+        let values = partition
+            .iter()
+            .map(|&row| rows[row][index].as_f64())
+            .collect::<Vec<_>>();
+        let computed = compute(table_calc.kind, &values);
+        for (&row, value) in partition.iter().zip(computed) {
+            rows[row][index] = value.map_or(DataValue::Empty, DataValue::Number);
+        }
+    }
+}
+
+/// Row indices of each partition, in result order.
+fn partitions(
+    rows: &[Vec<DataValue>],
+    dimension_count: usize,
+    scope: TableCalcScope,
+) -> Vec<Vec<usize>> {
+    let key_len = match scope {
+        TableCalcScope::Table => 0,
+        TableCalcScope::Partition => dimension_count.saturating_sub(1),
+    };
+    let mut order = Vec::new();
+    let mut members: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for (row_index, row) in rows.iter().enumerate() {
+        let key = row[..key_len]
+            .iter()
+            .map(DataValue::display_string)
+            .collect::<Vec<_>>();
+        members
+            .entry(key)
+            .or_insert_with_key(|key| {
+                order.push(key.clone());
+                Vec::new()
+            })
+            .push(row_index);
+    }
+    order
+        .into_iter()
+        .filter_map(|key| members.remove(&key))
+        .collect()
+}
+
+fn compute(kind: TableCalcKind, values: &[Option<f64>]) -> Vec<Option<f64>> {
+    match kind {
+        TableCalcKind::RunningSum => {
+            let mut total = 0.0;
+            values
+                .iter()
+                .map(|value| {
+                    let value = (*value)?;
+                    total += value;
+                    Some(total)
+                })
+                .collect()
+        }
+        TableCalcKind::PercentOfTotal => {
+            let total = values.iter().flatten().sum::<f64>();
+            values
+                .iter()
+                .map(|value| match value {
+                    Some(value) if total != 0.0 => Some(value / total * 100.0),
+                    _ => None,
+                })
+                .collect()
+        }
+        TableCalcKind::Difference | TableCalcKind::PercentDifference => values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let previous = values.get(index.checked_sub(1)?).copied().flatten()?;
+                let difference = (*value)? - previous;
+                match kind {
+                    TableCalcKind::Difference => Some(difference),
+                    _ if previous == 0.0 => None,
+                    _ => Some(difference / previous.abs() * 100.0),
+                }
+            })
+            .collect(),
+        TableCalcKind::Rank | TableCalcKind::DenseRank => {
+            let mut distinct = values.iter().flatten().copied().collect::<Vec<_>>();
+            distinct.sort_by(|left, right| right.total_cmp(left));
+            if kind == TableCalcKind::DenseRank {
+                distinct.dedup();
+            }
+            values
+                .iter()
+                .map(|value| {
+                    let value = (*value)?;
+                    let higher = distinct.partition_point(|other| *other > value);
+                    Some((higher + 1) as f64)
+                })
+                .collect()
+        }
+        TableCalcKind::MovingAverage(window) => {
+            let window = (window as usize).max(1);
+            (0..values.len())
+                .map(|index| {
+                    values[index]?;
+                    let start = (index + 1).saturating_sub(window);
+                    let present = values[start..=index].iter().flatten().collect::<Vec<_>>();
+                    Some(present.iter().copied().sum::<f64>() / present.len() as f64)
+                })
+                .collect()
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
 
+use crate::calc::field::CalculatedField;
+use crate::connectors::csv::CsvOptions;
+use crate::connectors::excel::ExcelOptions;
+use crate::connectors::schema::{InferenceOptions, TypeOverride};
 use crate::query::model::QuerySpec;
 use crate::viz::ChartType;
 
@@ -9,6 +13,18 @@ pub struct VizProject {
     pub sheet: Option<String>,
     pub query: QuerySpec,
     pub chart_type: ChartType,
+    #[serde(default)]
+    pub calculated_fields: Vec<CalculatedField>,
+    /// Header layouts for Excel sheets; re-applied when the workbook loads.
+    #[serde(default)]
+    pub excel: ExcelOptions,
+    /// Import options for delimited files; re-applied when the file loads.
+    #[serde(default)]
+    pub csv: CsvOptions,
+    #[serde(default)]
+    pub inference: InferenceOptions,
+    #[serde(default)]
+    pub type_overrides: Vec<TypeOverride>,
 }
 
 impl VizProject {
@@ -18,6 +34,11 @@ impl VizProject {
             sheet: None,
             query: QuerySpec::empty(),
             chart_type: ChartType::Table,
+            calculated_fields: Vec::new(),
+            excel: ExcelOptions::default(),
+            csv: CsvOptions::default(),
+            inference: InferenceOptions::default(),
+            type_overrides: Vec::new(),
         }
     }
 }
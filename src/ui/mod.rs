@@ -1,34 +1,125 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use chrono::NaiveDate;
 use eframe::egui;
 use egui::{Color32, RichText};
 use egui_plot::{Bar, BarChart, Legend, Line, Plot, PlotPoints, Points};
+use encoding_rs::{Encoding, ISO_8859_15, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
 
-use crate::connectors::excel;
-use crate::core::workbook::Workbook;
+use crate::calc::aggregate::AggregateCalc;
+use crate::calc::field::{CalculatedField, with_calculated_fields};
+use crate::calc::parser::CalcError;
+use crate::connectors::csv::CsvOptions;
+use crate::connectors::excel::{ExcelOptions, HeaderLayout};
+use crate::connectors::schema::{InferenceOptions, TypeOverride, apply_types};
+use crate::connectors::{self, DataFile, DELIMITED_EXTENSIONS, EXCEL_EXTENSIONS};
+use crate::core::data::DataType;
+use crate::core::workbook::{SheetInfo, SheetSource, Workbook, Worksheet};
+use crate::query::cache::{CacheKey, QueryCache};
 use crate::query::engine::{run_query, QueryResult};
-use crate::query::model::QuerySpec;
+use crate::query::filter;
+use crate::query::model::{
+    Aggregation, DateGrouping, Dimension, Filter, Limit, LimitDirection, Measure, NumericBins,
+    Predicate, QuerySpec, SortField, SortKey, SortOrder, TableCalc, TableCalcKind, TableCalcScope,
+};
 use crate::storage::project::{VizProject, load_project, save_project};
 use crate::viz::ChartType;
+use crate::worker::{Progress, Task, TaskStatus};
+
+/// How long calculated fields must stay unchanged before the sheet is
+/// prepared again, so typing a formula does not re-evaluate every keystroke.
+const FORMULA_DEBOUNCE: Duration = Duration::from_millis(400);
 
 pub struct VizualizerApp {
+    ctx: egui::Context,
     project: VizProject,
-    workbook: Option<Workbook>,
+    file: Option<DataFile>,
+    prepared: Option<PreparedSheet>,
+    /// Calculated fields that differ from the prepared ones, and when they
+    /// last changed.
+    pending_fields: Option<(Vec<CalculatedField>, Instant)>,
+    /// Each sheet's data version and the settings it was last prepared
+    /// with. A version is bumped only when its sheet is prepared with
+    /// different settings, so cached results from earlier data are not
+    /// reused while switching sheets keeps them.
+    sheet_versions: HashMap<String, (PrepareKey, u64)>,
+    query_cache: QueryCache,
+    /// The query behind `result`.
+    result_key: Option<CacheKey>,
+    result: Arc<QueryResult>,
+    jobs: Jobs,
     load_error: Option<String>,
 }
 
+/// The active sheet with column types applied and calculated fields
+/// evaluated, rebuilt whenever the sheet, the type settings or the field
+/// definitions change.
+struct PreparedSheet {
+    key: PrepareKey,
+    worksheet: Arc<Worksheet>,
+    errors: Vec<Option<CalcError>>,
+}
+
+#[derive(Clone, PartialEq)]
+struct PrepareKey {
+    sheet_name: String,
+    inference: InferenceOptions,
+    type_overrides: Vec<TypeOverride>,
+    calculated_fields: Vec<CalculatedField>,
+}
+
+/// Work running off the UI thread. Each slot holds at most one task;
+/// replacing or clearing a slot cancels the task in it.
+#[derive(Default)]
+struct Jobs {
+    opening: Option<OpenJob>,
+    loading_sheet: Option<(String, Task<anyhow::Result<Worksheet>>)>,
+    /// Holds `None` when the preparation was cancelled.
+    preparing: Option<(PrepareKey, Task<Option<PreparedSheet>>)>,
+    query: Option<(CacheKey, Task<Option<QueryResult>>)>,
+}
+
+struct OpenJob {
+    path: PathBuf,
+    reset_state: bool,
+    task: Task<anyhow::Result<DataFile>>,
+}
+
 impl VizualizerApp {
-    pub fn new() -> Self {
+    pub fn new(ctx: egui::Context) -> Self {
         Self {
+            ctx,
             project: VizProject::new(),
-            workbook: None,
+            file: None,
+            prepared: None,
+            pending_fields: None,
+            sheet_versions: HashMap::new(),
+            query_cache: QueryCache::default(),
+            result_key: None,
+            result: Arc::new(QueryResult::empty()),
+            jobs: Jobs::default(),
             load_error: None,
         }
     }
 
+    fn spawn<T: Send + 'static>(&self, work: impl FnOnce(&Progress) -> T + Send + 'static) -> Task<T> {
+        let ctx = self.ctx.clone();
+        Task::spawn(move || ctx.request_repaint(), work)
+    }
+
     pub fn open_dialog(&mut self) {
+        let all_extensions = EXCEL_EXTENSIONS
+            .iter()
+            .chain(DELIMITED_EXTENSIONS.iter())
+            .copied()
+            .collect::<Vec<_>>();
         if let Some(path) = rfd::FileDialog::new()
-            .add_filter("Excel", &["xlsx", "xls"])
+            .add_filter("Data files", &all_extensions)
+            .add_filter("Excel", &EXCEL_EXTENSIONS)
+            .add_filter("CSV / TSV", &DELIMITED_EXTENSIONS)
             .pick_file()
         {
             self.load_workbook(path, true);
@@ -40,10 +131,9 @@ impl VizualizerApp {
             .add_filter("Vizualizer Project", &["viz"])
             .set_file_name("project.viz")
             .save_file()
+            && let Err(error) = save_project(&path, &self.project)
         {
-            if let Err(error) = save_project(&path, &self.project) {
-                self.load_error = Some(error.to_string());
-            }
+            self.load_error = Some(error.to_string());
         }
     }
 
@@ -67,15 +157,41 @@ impl VizualizerApp {
         }
     }
 
+    /// Opens `path` in the background; the current file stays in use until
+    /// the new one is ready.
     fn load_workbook(&mut self, path: PathBuf, reset_state: bool) {
-        match excel::load_workbook(&path) {
-            Ok(workbook) => {
+        let (excel, csv) = if reset_state {
+            (ExcelOptions::default(), CsvOptions::for_path(&path))
+        } else {
+            (self.project.excel.clone(), self.project.csv.clone())
+        };
+        let task_path = path.clone();
+        let task = self.spawn(move |_| DataFile::open(&task_path, &excel, &csv));
+        self.jobs.opening = Some(OpenJob {
+            path,
+            reset_state,
+            task,
+        });
+    }
+
+    fn finish_open(&mut self, path: PathBuf, reset_state: bool, opened: anyhow::Result<DataFile>) {
+        match opened {
+            Ok(file) => {
                 self.project.workbook_path = Some(path.display().to_string());
                 if reset_state {
-                    self.project.sheet = workbook.sheets.first().map(|sheet| sheet.name.clone());
+                    let sources = &file.workbook().sources;
+                    self.project.excel = ExcelOptions::default();
+                    self.project.csv = CsvOptions::for_path(&path);
+                    self.project.sheet = sources.first().map(|source| source.name.clone());
                     self.project.query = QuerySpec::empty();
                 }
-                self.workbook = Some(workbook);
+                self.file = Some(file);
+                self.prepared = None;
+                self.sheet_versions.clear();
+                self.query_cache.clear();
+                self.result_key = None;
+                self.jobs.loading_sheet = None;
+                self.jobs.preparing = None;
                 self.load_error = None;
             }
             Err(error) => {
@@ -84,21 +200,276 @@ impl VizualizerApp {
         }
     }
 
-    fn active_sheet(&self) -> Option<&crate::core::workbook::Worksheet> {
-        let workbook = self.workbook.as_ref()?;
-        let sheet_name = self.project.sheet.as_ref()?;
-        workbook.sheets.iter().find(|sheet| &sheet.name == sheet_name)
+    /// Collects finished background work and starts whatever the current
+    /// selection needs next: opening the file, parsing the selected sheet,
+    /// preparing it and running the query.
+    fn poll_jobs(&mut self) {
+        if let Some(job) = &self.jobs.opening {
+            match job.task.poll() {
+                TaskStatus::Running => {}
+                TaskStatus::Finished(opened) => {
+                    let job = self.jobs.opening.take().expect("polled job");
+                    self.finish_open(job.path, job.reset_state, opened);
+                }
+                TaskStatus::Failed => {
+                    self.jobs.opening = None;
+                    self.load_error = Some("Opening the file failed unexpectedly".to_string());
+                }
+            }
+        }
+        self.poll_sheet_loading();
+        self.poll_preparing();
+        self.poll_query();
     }
 
-    fn query_result(&self) -> QueryResult {
-        if let Some(sheet) = self.active_sheet() {
-            run_query(sheet, &self.project.query)
-        } else {
-            QueryResult {
-                headers: Vec::new(),
-                rows: Vec::new(),
+    fn poll_sheet_loading(&mut self) {
+        let (Some(file), Some(sheet_name)) = (self.file.as_mut(), self.project.sheet.clone()) else {
+            self.jobs.loading_sheet = None;
+            return;
+        };
+        if let Some((name, task)) = &self.jobs.loading_sheet {
+            if *name != sheet_name {
+                self.jobs.loading_sheet = None;
+            } else {
+                match task.poll() {
+                    TaskStatus::Running => return,
+                    TaskStatus::Finished(loaded) => file.insert_sheet(name, loaded),
+                    TaskStatus::Failed => {
+                        file.insert_sheet(name, Err(anyhow::anyhow!("loading failed unexpectedly")))
+                    }
+                }
+                self.jobs.loading_sheet = None;
             }
         }
+        if let Some(loader) = file.sheet_loader(&sheet_name) {
+            let ctx = self.ctx.clone();
+            let task = Task::spawn(move || ctx.request_repaint(), move |progress| loader.load(progress));
+            self.jobs.loading_sheet = Some((sheet_name, task));
+        }
+    }
+
+    fn poll_preparing(&mut self) {
+        let Some(sheet) = self.source_sheet().cloned() else {
+            self.jobs.preparing = None;
+            return;
+        };
+        let key = PrepareKey {
+            sheet_name: sheet.name.clone(),
+            inference: self.project.inference,
+            type_overrides: self
+                .project
+                .type_overrides
+                .iter()
+                .filter(|item| item.sheet == sheet.name)
+                .cloned()
+                .collect(),
+            calculated_fields: self.project.calculated_fields.clone(),
+        };
+        if self.prepared.as_ref().is_some_and(|prepared| prepared.key == key) {
+            self.jobs.preparing = None;
+            self.pending_fields = None;
+            return;
+        }
+        if self.debounce_fields(&key) {
+            return;
+        }
+        if let Some((running, task)) = &self.jobs.preparing
+            && *running == key
+        {
+            match task.poll() {
+                TaskStatus::Running => {}
+                TaskStatus::Finished(None) => self.jobs.preparing = None,
+                TaskStatus::Finished(Some(prepared)) => {
+                    let sheet_name = prepared.key.sheet_name.clone();
+                    let version = match self.sheet_versions.get(&sheet_name) {
+                        Some((key, version)) if *key == prepared.key => *version,
+                        Some((_, version)) => version + 1,
+                        None => 0,
+                    };
+                    self.sheet_versions
+                        .insert(sheet_name.clone(), (prepared.key.clone(), version));
+                    self.query_cache.invalidate_sheet(&sheet_name, version);
+                    self.prepared = Some(prepared);
+                    self.jobs.preparing = None;
+                }
+                TaskStatus::Failed => {
+                    self.jobs.preparing = None;
+                    self.prepared = None;
+                    self.load_error = Some("Preparing the sheet failed unexpectedly".to_string());
+                }
+            }
+            return;
+        }
+        let task_key = key.clone();
+        let task = self.spawn(move |progress| {
+            let typed = apply_types(
+                &sheet,
+                &task_key.inference,
+                &task_key.type_overrides,
+                &progress.part(0.0, 0.5),
+            )?;
+            let (worksheet, errors) =
+                with_calculated_fields(&typed, &task_key.calculated_fields, &progress.part(0.5, 1.0))?;
+            Some(PreparedSheet {
+                key: task_key,
+                worksheet: Arc::new(worksheet),
+                errors,
+            })
+        });
+        self.jobs.preparing = Some((key, task));
+    }
+
+    /// Whether to hold off preparing `key` because only its calculated
+    /// fields changed, and did so within `FORMULA_DEBOUNCE`. A preparation
+    /// for earlier fields is cancelled meanwhile.
+    fn debounce_fields(&mut self, key: &PrepareKey) -> bool {
+        let only_fields_changed = self.prepared.as_ref().is_some_and(|prepared| {
+            prepared.key.sheet_name == key.sheet_name
+                && prepared.key.inference == key.inference
+                && prepared.key.type_overrides == key.type_overrides
+        });
+        if !only_fields_changed {
+            self.pending_fields = None;
+            return false;
+        }
+        let now = Instant::now();
+        let changed_at = match &self.pending_fields {
+            Some((fields, changed_at)) if *fields == key.calculated_fields => *changed_at,
+            _ => {
+                self.pending_fields = Some((key.calculated_fields.clone(), now));
+                now
+            }
+        };
+        let waited = now.duration_since(changed_at);
+        if waited >= FORMULA_DEBOUNCE {
+            return false;
+        }
+        if self.jobs.preparing.as_ref().is_some_and(|(running, _)| running != key) {
+            self.jobs.preparing = None;
+        }
+        self.ctx.request_repaint_after(FORMULA_DEBOUNCE - waited);
+        true
+    }
+
+    fn poll_query(&mut self) {
+        // Keep showing the last result until the sheet is prepared, rather
+        // than querying the untyped sheet in between.
+        if self.jobs.preparing.is_some() {
+            return;
+        }
+        let Some(sheet) = self.active_sheet().cloned() else {
+            self.jobs.query = None;
+            self.result_key = None;
+            self.result = Arc::new(QueryResult::empty());
+            return;
+        };
+        let version = self.sheet_versions.get(&sheet.name).map_or(0, |(_, version)| *version);
+        let key = CacheKey::new(&sheet.name, version, &self.project.query);
+        if self.result_key.as_ref() == Some(&key) {
+            self.jobs.query = None;
+            return;
+        }
+        if let Some(result) = self.query_cache.get(&key) {
+            self.result = result;
+            self.result_key = Some(key);
+            self.jobs.query = None;
+            return;
+        }
+        if let Some((running, task)) = &self.jobs.query
+            && *running == key
+        {
+            match task.poll() {
+                TaskStatus::Running => {}
+                TaskStatus::Finished(result) => {
+                    self.result = Arc::new(result.unwrap_or_else(QueryResult::empty));
+                    self.query_cache.insert(key.clone(), Arc::clone(&self.result));
+                    self.result_key = Some(key);
+                    self.jobs.query = None;
+                }
+                TaskStatus::Failed => {
+                    self.result = Arc::new(QueryResult::empty());
+                    self.result_key = Some(key);
+                    self.jobs.query = None;
+                    self.load_error = Some("The query failed unexpectedly".to_string());
+                }
+            }
+            return;
+        }
+        let spec = self.project.query.clone();
+        let task = self.spawn(move |progress| {
+            run_query(&sheet, &spec, &|fraction| progress.report(fraction))
+        });
+        self.jobs.query = Some((key, task));
+    }
+
+    /// Spinners, progress and cancel buttons for the running jobs.
+    fn job_status(&mut self, ui: &mut egui::Ui) {
+        if let Some(job) = &self.jobs.opening {
+            let label = format!("Opening {}…", job.path.display());
+            if busy_row(ui, &label, None) {
+                self.jobs.opening = None;
+            }
+        }
+        if let Some((sheet_name, task)) = &self.jobs.loading_sheet
+            && busy_row(ui, &format!("Loading {sheet_name}…"), Some(task.progress()))
+        {
+            self.jobs.loading_sheet = None;
+            self.project.sheet = None;
+        }
+        if let Some((_, task)) = &self.jobs.preparing {
+            ui.horizontal(|ui| {
+                ui.add(egui::ProgressBar::new(task.progress()).desired_width(120.0));
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+                ui.label("Applying column types and calculated fields…");
+            });
+        }
+        if let Some((key, task)) = &self.jobs.query
+            && busy_row(ui, "Running query…", Some(task.progress()))
+        {
+            // Keep the cancelled query from being started again.
+            self.result_key = Some(key.clone());
+            self.result = Arc::new(QueryResult::empty());
+            self.jobs.query = None;
+        }
+    }
+
+    fn source_sheet(&self) -> Option<&Arc<Worksheet>> {
+        let file = self.file.as_ref()?;
+        file.workbook().sheet(self.project.sheet.as_ref()?)
+    }
+
+    fn active_sheet(&self) -> Option<&Arc<Worksheet>> {
+        match &self.prepared {
+            Some(prepared) if self.project.sheet.as_ref() == Some(&prepared.key.sheet_name) => {
+                Some(&prepared.worksheet)
+            }
+            _ => self.source_sheet(),
+        }
+    }
+
+    fn calculated_field_errors(&self) -> Vec<Option<String>> {
+        let errors = self.prepared.as_ref().map(|prepared| &prepared.errors);
+        (0..self.project.calculated_fields.len())
+            .map(|index| {
+                errors
+                    .and_then(|errors| errors.get(index).cloned().flatten())
+                    .map(|error| error.to_string())
+            })
+            .collect()
+    }
+
+    fn aggregate_calculation_errors(&self) -> Vec<Option<String>> {
+        let columns = self
+            .active_sheet()
+            .map(|sheet| sheet.columns.as_slice())
+            .unwrap_or_default();
+        self.project
+            .query
+            .calculations
+            .iter()
+            .map(|field| AggregateCalc::compile(field, columns).err())
+            .map(|error| error.map(|error| error.to_string()))
+            .collect()
     }
 
     fn render_chart(&self, ui: &mut egui::Ui, result: &QueryResult) {
@@ -108,15 +479,18 @@ impl VizualizerApp {
             ChartType::Line => render_line(ui, result),
             ChartType::Pie => render_pie(ui, result),
             ChartType::Scatter => render_scatter(ui, result),
+            ChartType::Histogram => render_histogram(ui, result),
         }
     }
 }
 
 impl eframe::App for VizualizerApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_jobs();
+
         egui::TopBottomPanel::top("top-bar").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                if ui.button("Open Excel").clicked() {
+                if ui.button("Open Data").clicked() {
                     self.open_dialog();
                 }
                 if ui.button("Load Project").clicked() {
@@ -131,55 +505,190 @@ impl eframe::App for VizualizerApp {
             });
         });
 
+        let calculated_errors = self.calculated_field_errors();
+        let aggregate_errors = self.aggregate_calculation_errors();
+        let column_types = self
+            .active_sheet()
+            .map(|sheet| {
+                sheet
+                    .columns
+                    .iter()
+                    .map(|column| (column.name.clone(), column.data_type.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut reload_workbook = false;
         egui::SidePanel::left("fields-panel")
             .resizable(true)
             .show(ctx, |ui| {
                 ui.heading("Fields");
 
-                if let Some(workbook) = &self.workbook {
-                    if workbook.sheets.is_empty() {
+                if let Some(file) = &self.file {
+                    let workbook = file.workbook();
+                    if workbook.sources.is_empty() {
                         ui.label("Workbook has no sheets.");
                         return;
                     }
 
                     ui.label("Sheet");
-                    egui::ComboBox::from_id_source("sheet-selector")
-                        .selected_text(self.project.sheet.clone().unwrap_or_default())
+                    let selected = workbook
+                        .sources
+                        .iter()
+                        .find(|source| self.project.sheet.as_ref() == Some(&source.name));
+                    egui::ComboBox::from_id_salt("sheet-selector")
+                        .selected_text(selected.map(SheetInfo::display_name).unwrap_or_default())
                         .show_ui(ui, |ui| {
-                            for sheet in &workbook.sheets {
+                            for source in &workbook.sources {
                                 ui.selectable_value(
                                     &mut self.project.sheet,
-                                    Some(sheet.name.clone()),
-                                    &sheet.name,
+                                    Some(source.name.clone()),
+                                    source.display_name(),
                                 );
                             }
                         });
+                    if workbook.sources.len() > 1 {
+                        ui.weak(format!(
+                            "{} of {} sheets loaded",
+                            workbook.sheets.len(),
+                            workbook.sources.len()
+                        ));
+                    }
 
-                    let sheet = self.active_sheet().unwrap_or(&workbook.sheets[0]);
-                    let column_names = sheet
+                    let is_excel = self
+                        .project
+                        .workbook_path
+                        .as_ref()
+                        .is_some_and(|path| !connectors::is_delimited(Path::new(path)));
+                    // Tables carry their own header, so only sheets and
+                    // defined names get a header layout.
+                    let has_header_layout = selected
+                        .is_some_and(|source| !matches!(source.source, SheetSource::Table { .. }));
+                    if !is_excel && csv_options_editor(ui, &mut self.project.csv) {
+                        reload_workbook = true;
+                    }
+                    if is_excel
+                        && ui
+                            .checkbox(
+                                &mut self.project.excel.drop_overflow_columns,
+                                "Drop columns beyond the header",
+                            )
+                            .changed()
+                    {
+                        reload_workbook = true;
+                    }
+                    if is_excel
+                        && has_header_layout
+                        && let Some(sheet_name) = self.project.sheet.clone()
+                    {
+                        let headers = &mut self.project.excel.headers;
+                        let mut layout = headers.get(&sheet_name).copied().unwrap_or_default();
+                        if header_layout_editor(ui, &mut layout) {
+                            if layout == HeaderLayout::default() {
+                                headers.remove(&sheet_name);
+                            } else {
+                                headers.insert(sheet_name, layout);
+                            }
+                            reload_workbook = true;
+                        }
+                    }
+
+                    let Some(sheet) = self.active_sheet() else {
+                        if self.jobs.loading_sheet.is_some() {
+                            ui.spinner();
+                        }
+                        load_report(ui, workbook, None);
+                        return;
+                    };
+                    load_report(ui, workbook, Some(sheet));
+                    let columns = sheet
                         .columns
                         .iter()
-                        .map(|column| column.name.clone())
+                        .map(|column| {
+                            let first_value = column.value(0).display_string();
+                            let predicate = Predicate::default_for(&column.data_type, first_value);
+                            let aggregation = Aggregation::default_for(&column.data_type);
+                            let dimension =
+                                Dimension::default_for(column.name.clone(), &column.data_type);
+                            (column.name.clone(), dimension, predicate, aggregation)
+                        })
                         .collect::<Vec<_>>();
+                    let sheet_name = sheet.name.clone();
+                    let source_columns = self
+                        .source_sheet()
+                        .map(|sheet| {
+                            sheet
+                                .columns
+                                .iter()
+                                .map(|column| column.name.clone())
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default();
 
                     ui.separator();
                     ui.label(RichText::new("Dimensions").strong());
-                    for column_name in column_names {
+                    for (column_name, dimension, predicate, aggregation) in columns {
                         ui.horizontal(|ui| {
                             ui.label(&column_name);
-                            if ui.small_button("Rows").clicked() {
-                                add_unique(&mut self.project.query.rows, &column_name);
+                            if source_columns.contains(&column_name) {
+                                ui.push_id(&column_name, |ui| {
+                                    type_override_picker(
+                                        ui,
+                                        &mut self.project.type_overrides,
+                                        &sheet_name,
+                                        &column_name,
+                                    );
+                                });
+                            }
+                            if ui.small_button("Rows").clicked()
+                                && !self.project.query.rows.contains(&dimension)
+                            {
+                                self.project.query.rows.push(dimension);
                             }
                             if ui.small_button("Columns").clicked() {
-                                add_unique(&mut self.project.query.columns, &column_name);
+                                let measure = Measure::new(column_name.clone(), aggregation);
+                                if !self.project.query.columns.contains(&measure) {
+                                    self.project.query.columns.push(measure);
+                                }
+                            }
+                            if ui.small_button("Filter").clicked() {
+                                self.project.query.filters.push(Filter {
+                                    column: column_name.clone(),
+                                    predicate,
+                                });
                             }
                         });
                     }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Tolerated parse errors");
+                        let mut percent = self.project.inference.error_threshold * 100.0;
+                        if ui
+                            .add(egui::DragValue::new(&mut percent).range(0.0..=50.0).suffix("%"))
+                            .changed()
+                        {
+                            self.project.inference.error_threshold = percent / 100.0;
+                        }
+                    });
+
+                    ui.separator();
+                    ui.label(RichText::new("Calculated fields").strong());
+                    calculated_fields_editor(
+                        ui,
+                        &mut self.project.calculated_fields,
+                        &calculated_errors,
+                        "e.g. [Sales] * 0.2",
+                        "Add calculated field",
+                        false,
+                    );
                 } else {
                     ui.label("Open a workbook to inspect fields.");
                 }
             });
 
+        if reload_workbook && let Some(path) = self.project.workbook_path.clone() {
+            self.load_workbook(PathBuf::from(path), false);
+        }
+
         egui::SidePanel::right("config-panel")
             .resizable(true)
             .show(ctx, |ui| {
@@ -187,10 +696,42 @@ impl eframe::App for VizualizerApp {
                 ui.separator();
 
                 ui.label("Rows shelf");
-                shelf_editor(ui, &mut self.project.query.rows);
+                let query = &mut self.project.query;
+                let mut changed_fields = Vec::new();
+                if let Some(field) = shelf_editor(ui, &mut query.rows, &column_types, &mut changed_fields) {
+                    query.toggle_sort(field);
+                }
 
                 ui.label("Columns shelf");
-                shelf_editor(ui, &mut self.project.query.columns);
+                if let Some(field) = measure_shelf_editor(ui, &mut query.columns, &mut changed_fields) {
+                    query.toggle_sort(field);
+                }
+                for (old, new) in changed_fields {
+                    query.replace_field(&old, new.as_ref());
+                }
+
+                ui.label("Aggregate calculations");
+                if let Some(index) = calculated_fields_editor(
+                    ui,
+                    &mut query.calculations,
+                    &aggregate_errors,
+                    "e.g. SUM([Profit]) / SUM([Sales])",
+                    "Add aggregate calculation",
+                    true,
+                ) {
+                    let name = query.calculations[index].name.clone();
+                    query.toggle_sort(SortField::Calculation(name));
+                }
+
+                ui.label("Sort");
+                let shelf_fields = self.project.query.shelf_fields();
+                sort_editor(ui, &mut self.project.query.sort, &shelf_fields);
+
+                ui.label("Limit");
+                limit_editor(ui, &mut self.project.query);
+
+                ui.label("Filters shelf");
+                filter_editor(ui, &mut self.project.query.filters);
 
                 ui.separator();
                 ui.label("Chart type");
@@ -206,49 +747,728 @@ impl eframe::App for VizualizerApp {
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Visualization");
-            let result = self.query_result();
-            self.render_chart(ui, &result);
+            self.job_status(ui);
+            if let Some(error) = &self.result.error {
+                ui.colored_label(Color32::RED, error);
+            }
+            self.render_chart(ui, &self.result);
         });
     }
 }
 
-fn add_unique(list: &mut Vec<String>, value: &str) {
-    if !list.iter().any(|item| item == value) {
-        list.push(value.to_string());
+/// Edits named formulas; when `sortable`, returns the index of the field
+/// whose sort button was clicked, if any.
+fn calculated_fields_editor(
+    ui: &mut egui::Ui,
+    fields: &mut Vec<CalculatedField>,
+    errors: &[Option<String>],
+    hint: &str,
+    add_label: &str,
+    sortable: bool,
+) -> Option<usize> {
+    let mut remove_index = None;
+    let mut sort_request = None;
+    for (index, field) in fields.iter_mut().enumerate() {
+        ui.push_id((add_label, index), |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut field.name)
+                        .hint_text("Name")
+                        .desired_width(120.0),
+                );
+                if sortable && ui.small_button("Sort").clicked() {
+                    sort_request = Some(index);
+                }
+                if ui.small_button("Remove").clicked() {
+                    remove_index = Some(index);
+                }
+            });
+            ui.add(
+                egui::TextEdit::multiline(&mut field.formula)
+                    .hint_text(hint)
+                    .code_editor()
+                    .desired_rows(2),
+            );
+            if let Some(Some(error)) = errors.get(index) {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
     }
+    if let Some(index) = remove_index {
+        fields.remove(index);
+    }
+    if ui.small_button(add_label).clicked() {
+        fields.push(CalculatedField {
+            name: format!("Calculation {}", fields.len() + 1),
+            formula: String::new(),
+        });
+    }
+    sort_request
+}
+
+enum DrillAction {
+    Expand(usize, DateGrouping),
+    Collapse(usize),
 }
 
-fn shelf_editor(ui: &mut egui::Ui, items: &mut Vec<String>) {
+const TYPE_CHOICES: [DataType; 7] = [
+    DataType::String,
+    DataType::Number,
+    DataType::Date,
+    DataType::DateTime,
+    DataType::Time,
+    DataType::Duration,
+    DataType::Boolean,
+];
+
+/// Lets the user pin a source column's type instead of inferring it.
+fn type_override_picker(
+    ui: &mut egui::Ui,
+    overrides: &mut Vec<TypeOverride>,
+    sheet: &str,
+    column: &str,
+) {
+    let position = overrides
+        .iter()
+        .position(|item| item.sheet == sheet && item.column == column);
+    let mut selected = position.map(|index| overrides[index].data_type.clone());
+    let label = |data_type: &Option<DataType>| match data_type {
+        Some(data_type) => format!("{data_type:?}"),
+        None => "Automatic".to_string(),
+    };
+    egui::ComboBox::from_id_salt("type-override")
+        .selected_text(label(&selected))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut selected, None, label(&None));
+            for data_type in TYPE_CHOICES {
+                let text = label(&Some(data_type.clone()));
+                ui.selectable_value(&mut selected, Some(data_type), text);
+            }
+        });
+    match (position, selected) {
+        (Some(index), None) => {
+            overrides.remove(index);
+        }
+        (Some(index), Some(data_type)) => overrides[index].data_type = data_type,
+        (None, Some(data_type)) => overrides.push(TypeOverride {
+            sheet: sheet.to_string(),
+            column: column.to_string(),
+            data_type,
+        }),
+        (None, None) => {}
+    }
+}
+
+/// Lists Rows shelf dimensions with remove buttons, plus grouping and drill
+/// controls for Date columns and a bins picker for Number and Duration
+/// columns; returns the sort field of the dimension whose sort button was
+/// clicked, if any.
+/// Regrouped or removed dimensions are added to `changed` with their old
+/// and new sort fields.
+fn shelf_editor(
+    ui: &mut egui::Ui,
+    dimensions: &mut Vec<Dimension>,
+    column_types: &[(String, DataType)],
+    changed: &mut Vec<(SortField, Option<SortField>)>,
+) -> Option<SortField> {
     let mut remove_index = None;
-    for (index, item) in items.iter().enumerate() {
-        ui.horizontal(|ui| {
-            ui.label(item);
-            if ui.small_button("Remove").clicked() {
-                remove_index = Some(index);
+    let mut sort_request = None;
+    let mut drill = None;
+    for index in 0..dimensions.len() {
+        let next = dimensions.get(index + 1).cloned();
+        let dimension = &mut dimensions[index];
+        let before = dimension.sort_field();
+        let data_type = column_types
+            .iter()
+            .find(|(name, _)| *name == dimension.column)
+            .map(|(_, data_type)| data_type.clone());
+        ui.push_id(("dimension", index), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(&dimension.column);
+                if matches!(data_type, Some(DataType::Number | DataType::Duration)) {
+                    numeric_bins_picker(ui, &mut dimension.bins);
+                }
+                if data_type.as_ref().is_some_and(DataType::is_temporal) {
+                    let with_time = data_type == Some(DataType::DateTime);
+                    date_grouping_picker(ui, &mut dimension.date, with_time);
+                    let next_level = dimension.date.and_then(|date| date.drill_down(with_time));
+                    let drilled = next.is_some_and(|next| {
+                        next.column == dimension.column
+                            && next.date.is_some()
+                            && next.date == next_level
+                    });
+                    if drilled {
+                        if ui.small_button("−").on_hover_text("Collapse").clicked() {
+                            drill = Some(DrillAction::Collapse(index));
+                        }
+                    } else if let Some(level) = next_level
+                        && ui.small_button("+").on_hover_text("Drill down").clicked()
+                    {
+                        drill = Some(DrillAction::Expand(index, level));
+                    }
+                }
+                if ui.small_button("Sort").clicked() {
+                    sort_request = Some(dimension.sort_field());
+                }
+                if ui.small_button("Remove").clicked() {
+                    remove_index = Some(index);
+                }
+            });
+            if let Some(bins) = &mut dimension.bins {
+                numeric_bins_editor(ui, bins);
             }
         });
+        if dimension.sort_field() != before {
+            changed.push((before, Some(dimension.sort_field())));
+        }
+    }
+    match drill {
+        Some(DrillAction::Expand(index, level)) => {
+            let column = dimensions[index].column.clone();
+            dimensions.insert(
+                index + 1,
+                Dimension {
+                    date: Some(level),
+                    ..Dimension::new(column)
+                },
+            );
+        }
+        Some(DrillAction::Collapse(index)) => {
+            let column = dimensions[index].column.clone();
+            let drill_down = |date: DateGrouping| date.drill_down(true);
+            let mut level = dimensions[index].date.and_then(drill_down);
+            while level.is_some()
+                && dimensions
+                    .get(index + 1)
+                    .is_some_and(|next| next.column == column && next.date == level)
+            {
+                changed.push((dimensions.remove(index + 1).sort_field(), None));
+                level = level.and_then(drill_down);
+            }
+        }
+        None => {}
     }
     if let Some(index) = remove_index {
-        items.remove(index);
+        changed.push((dimensions.remove(index).sort_field(), None));
     }
+    sort_request
+}
+
+fn numeric_bins_picker(ui: &mut egui::Ui, bins: &mut Option<NumericBins>) {
+    let selected = bins.as_ref().map_or("No bins", NumericBins::label);
+    egui::ComboBox::from_id_salt("numeric-bins")
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            if ui.selectable_label(bins.is_none(), "No bins").clicked() {
+                *bins = None;
+            }
+            for template in NumericBins::templates() {
+                let is_selected = bins.as_ref().is_some_and(|bins| bins.same_kind(&template));
+                let label = template.label();
+                if ui.selectable_label(is_selected, label).clicked() && !is_selected {
+                    *bins = Some(template);
+                }
+            }
+        });
+}
+
+fn numeric_bins_editor(ui: &mut egui::Ui, bins: &mut NumericBins) {
+    ui.horizontal(|ui| match bins {
+        NumericBins::Width(width) => {
+            ui.label("Width");
+            ui.add(egui::DragValue::new(width).speed(1.0).range(0.0..=f64::MAX));
+        }
+        NumericBins::Count(count) | NumericBins::Quantile(count) => {
+            ui.label("Bins");
+            ui.add(egui::DragValue::new(count).range(1..=1000));
+        }
+        NumericBins::Edges(edges) => {
+            let id = ui.id().with("bin-edges");
+            let mut text = ui.data_mut(|data| {
+                data.get_temp::<String>(id).unwrap_or_else(|| {
+                    edges
+                        .iter()
+                        .map(|edge| edge.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+            });
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut text)
+                    .hint_text("edges, comma separated")
+                    .desired_width(180.0),
+            );
+            if response.changed() {
+                *edges = text
+                    .split(',')
+                    .filter_map(|edge| edge.trim().parse::<f64>().ok())
+                    .collect();
+            }
+            ui.data_mut(|data| data.insert_temp(id, text));
+        }
+    });
+}
+
+fn date_grouping_picker(ui: &mut egui::Ui, grouping: &mut Option<DateGrouping>, with_time: bool) {
+    let selected = grouping.map_or("Exact date".to_string(), DateGrouping::label);
+    egui::ComboBox::from_id_salt("date-grouping")
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(grouping, None, "Exact date");
+            for option in DateGrouping::options(with_time) {
+                ui.selectable_value(grouping, Some(option), option.label());
+            }
+        });
+}
+
+/// Lists Columns shelf measures, each with its own aggregation picker;
+/// returns the sort field of the measure whose sort button was clicked.
+/// Re-aggregated or removed measures are added to `changed` like in
+/// `shelf_editor`.
+fn measure_shelf_editor(
+    ui: &mut egui::Ui,
+    measures: &mut Vec<Measure>,
+    changed: &mut Vec<(SortField, Option<SortField>)>,
+) -> Option<SortField> {
+    let mut remove_index = None;
+    let mut sort_request = None;
+    for (index, measure) in measures.iter_mut().enumerate() {
+        let before = measure.sort_field();
+        ui.push_id(("measure", index), |ui| {
+            ui.horizontal(|ui| {
+                aggregation_picker(ui, &mut measure.aggregation);
+                ui.label(&measure.column);
+                if ui.small_button("Sort").clicked() {
+                    sort_request = Some(measure.sort_field());
+                }
+                if ui.small_button("Remove").clicked() {
+                    remove_index = Some(index);
+                }
+            });
+            table_calc_picker(ui, &mut measure.table_calc);
+        });
+        if measure.sort_field() != before {
+            changed.push((before, Some(measure.sort_field())));
+        }
+    }
+    if let Some(index) = remove_index {
+        changed.push((measures.remove(index).sort_field(), None));
+    }
+    sort_request
+}
+
+fn table_calc_picker(ui: &mut egui::Ui, table_calc: &mut Option<TableCalc>) {
+    ui.horizontal(|ui| {
+        let selected = table_calc.map_or("No table calculation", |calc| calc.kind.label());
+        egui::ComboBox::from_id_salt("table-calc")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(table_calc.is_none(), "No table calculation")
+                    .clicked()
+                {
+                    *table_calc = None;
+                }
+                for kind in TableCalcKind::ALL {
+                    let is_selected = table_calc.is_some_and(|calc| calc.kind.same_kind(kind));
+                    if ui.selectable_label(is_selected, kind.label()).clicked() && !is_selected {
+                        *table_calc = Some(TableCalc {
+                            kind,
+                            scope: table_calc.map_or(TableCalcScope::Table, |calc| calc.scope),
+                        });
+                    }
+                }
+            });
+        let Some(calc) = table_calc else {
+            return;
+        };
+        if let TableCalcKind::MovingAverage(window) = &mut calc.kind {
+            ui.add(egui::DragValue::new(window).range(1..=100).suffix(" rows"));
+        }
+        egui::ComboBox::from_id_salt("table-calc-scope")
+            .selected_text(calc.scope.label())
+            .show_ui(ui, |ui| {
+                for scope in TableCalcScope::ALL {
+                    ui.selectable_value(&mut calc.scope, scope, scope.label());
+                }
+            });
+    });
+}
+
+fn aggregation_picker(ui: &mut egui::Ui, aggregation: &mut Aggregation) {
+    egui::ComboBox::from_id_salt("aggregation")
+        .selected_text(aggregation.short_label())
+        .show_ui(ui, |ui| {
+            for candidate in Aggregation::COMMON {
+                ui.selectable_value(aggregation, candidate, candidate.label());
+            }
+        });
+    if let Aggregation::Percentile(p) = aggregation {
+        ui.add(egui::DragValue::new(p).range(1..=99).prefix("P"));
+    }
+}
+
+fn limit_editor(ui: &mut egui::Ui, query: &mut QuerySpec) {
+    let measures = query
+        .columns
+        .iter()
+        .map(Measure::sort_field)
+        .chain(
+            query
+                .calculations
+                .iter()
+                .map(|field| SortField::Calculation(field.name.clone())),
+        )
+        .collect::<Vec<_>>();
+
+    let mut enabled = query.limit.is_some();
+    if ui.checkbox(&mut enabled, "Keep only").changed() {
+        query.limit = match (enabled, measures.first()) {
+            (true, Some(by)) => Some(Limit {
+                direction: LimitDirection::Top,
+                count: 10,
+                by: by.clone(),
+                other_bucket: true,
+            }),
+            _ => None,
+        };
+    }
+    let Some(limit) = &mut query.limit else {
+        return;
+    };
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("limit-direction")
+            .selected_text(limit.direction.label())
+            .show_ui(ui, |ui| {
+                for direction in LimitDirection::ALL {
+                    ui.selectable_value(&mut limit.direction, direction, direction.label());
+                }
+            });
+        ui.add(egui::DragValue::new(&mut limit.count).range(1..=1000));
+        ui.label("by");
+        egui::ComboBox::from_id_salt("limit-by")
+            .selected_text(limit.by.label())
+            .show_ui(ui, |ui| {
+                for measure in &measures {
+                    ui.selectable_value(&mut limit.by, measure.clone(), measure.label());
+                }
+            });
+        if !measures.contains(&limit.by) {
+            ui.colored_label(Color32::RED, "not on a shelf");
+        }
+    });
+    ui.checkbox(&mut limit.other_bucket, "Group the rest as Other");
+}
+
+/// Edits the sort keys, flagging keys on fields no longer on a shelf, which
+/// the query cannot apply.
+fn sort_editor(ui: &mut egui::Ui, keys: &mut Vec<SortKey>, shelf_fields: &[SortField]) {
+    if keys.is_empty() {
+        ui.weak("Ordered by dimensions");
+    }
+    let mut remove_index = None;
+    for (index, key) in keys.iter_mut().enumerate() {
+        ui.push_id(("sort", index), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(key.field.label());
+                if !shelf_fields.is_empty() && !shelf_fields.contains(&key.field) {
+                    ui.colored_label(Color32::RED, "not on a shelf");
+                }
+                if ui.small_button(key.direction.label()).clicked() {
+                    key.direction = key.direction.toggled();
+                }
+                let mut manual = matches!(key.order, SortOrder::Manual(_));
+                if ui.checkbox(&mut manual, "Manual").changed() {
+                    key.order = if manual {
+                        SortOrder::Manual(Vec::new())
+                    } else {
+                        SortOrder::Natural
+                    };
+                }
+                if ui.small_button("Remove").clicked() {
+                    remove_index = Some(index);
+                }
+            });
+            if let SortOrder::Manual(order) = &mut key.order {
+                let mut joined = order.join(", ");
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut joined)
+                        .hint_text("values in order, comma separated")
+                        .desired_width(180.0),
+                );
+                if response.changed() {
+                    *order = joined.split(',').map(|item| item.trim().to_string()).collect();
+                }
+            }
+        });
+    }
+    if let Some(index) = remove_index {
+        keys.remove(index);
+    }
+}
+
+fn filter_editor(ui: &mut egui::Ui, filters: &mut Vec<Filter>) {
+    let mut remove_index = None;
+    for (index, filter) in filters.iter_mut().enumerate() {
+        ui.push_id(index, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(&filter.column);
+                egui::ComboBox::from_id_salt("predicate-kind")
+                    .selected_text(filter.predicate.label())
+                    .show_ui(ui, |ui| {
+                        for template in Predicate::templates() {
+                            let selected = filter.predicate.same_kind(&template);
+                            let label = template.label();
+                            if ui.selectable_label(selected, label).clicked() && !selected {
+                                filter.predicate = template;
+                            }
+                        }
+                    });
+                if ui.small_button("Remove").clicked() {
+                    remove_index = Some(index);
+                }
+            });
+            predicate_editor(ui, &mut filter.predicate);
+            if let Some(problem) = filter::validate(&filter.predicate) {
+                ui.colored_label(Color32::RED, problem);
+            }
+        });
+    }
+    if let Some(index) = remove_index {
+        filters.remove(index);
+    }
+}
+
+fn predicate_editor(ui: &mut egui::Ui, predicate: &mut Predicate) {
+    match predicate {
+        Predicate::Equals(text) | Predicate::Contains(text) | Predicate::Matches(text) => {
+            ui.add(egui::TextEdit::singleline(text).desired_width(140.0));
+        }
+        Predicate::In(options) | Predicate::NotIn(options) => {
+            let mut joined = options.join(", ");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut joined)
+                    .hint_text("comma separated")
+                    .desired_width(140.0),
+            );
+            if response.changed() {
+                *options = joined.split(',').map(|item| item.trim().to_string()).collect();
+            }
+        }
+        Predicate::Between { min, max } => {
+            ui.horizontal(|ui| {
+                optional_number(ui, "min", min);
+                optional_number(ui, "max", max);
+            });
+        }
+        Predicate::DateBetween { start, end } => {
+            ui.horizontal(|ui| {
+                optional_date(ui, "from", start);
+                optional_date(ui, "to", end);
+            });
+        }
+        Predicate::LastDays(days) => {
+            ui.add(egui::DragValue::new(days).range(1..=3650).suffix(" days"));
+        }
+        Predicate::IsTrue | Predicate::IsFalse | Predicate::IsEmpty | Predicate::NotEmpty => {}
+    }
+}
+
+/// A spinner, or a progress bar when `progress` is known, with a cancel
+/// button. Returns whether cancel was clicked.
+fn busy_row(ui: &mut egui::Ui, label: &str, progress: Option<f32>) -> bool {
+    ui.horizontal(|ui| {
+        match progress {
+            Some(fraction) => {
+                ui.add(egui::ProgressBar::new(fraction).desired_width(120.0));
+                // Workers only wake the UI when they finish.
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+            }
+            None => {
+                ui.spinner();
+            }
+        }
+        ui.label(label);
+        ui.small_button("Cancel").clicked()
+    })
+    .inner
+}
+
+/// Lists sheets that could not be read and what loading and typing changed
+/// in the active sheet.
+fn load_report(ui: &mut egui::Ui, workbook: &Workbook, sheet: Option<&Worksheet>) {
+    let messages = workbook
+        .skipped_sheets
+        .iter()
+        .map(|(name, reason)| format!("Skipped sheet {name}: {reason}"))
+        .chain(sheet.map(|sheet| sheet.diagnostics.messages()).unwrap_or_default())
+        .collect::<Vec<_>>();
+    if messages.is_empty() {
+        return;
+    }
+    egui::CollapsingHeader::new(format!("Load report ({})", messages.len()))
+        .id_salt("load-report")
+        .show(ui, |ui| {
+            for message in &messages {
+                ui.colored_label(Color32::YELLOW, message);
+            }
+        });
+}
+
+const DELIMITER_CHOICES: [(char, &str); 4] =
+    [(',', "Comma"), ('\t', "Tab"), (';', "Semicolon"), ('|', "Pipe")];
+const QUOTE_CHOICES: [(char, &str); 2] = [('"', "Double (\")"), ('\'', "Single (')")];
+const ENCODING_CHOICES: [&Encoding; 5] = [UTF_8, UTF_16LE, UTF_16BE, WINDOWS_1252, ISO_8859_15];
+
+/// Import options for a delimited file, where unset choices are detected;
+/// returns whether any changed.
+fn csv_options_editor(ui: &mut egui::Ui, options: &mut CsvOptions) -> bool {
+    let before = options.clone();
+    egui::CollapsingHeader::new("Import options").show(ui, |ui| {
+        egui::Grid::new("csv-options").num_columns(2).show(ui, |ui| {
+            ui.label("Delimiter");
+            let label = |delimiter: Option<char>| {
+                DELIMITER_CHOICES
+                    .iter()
+                    .find(|(choice, _)| Some(*choice) == delimiter)
+                    .map_or("Detect", |(_, label)| label)
+            };
+            egui::ComboBox::from_id_salt("csv-delimiter")
+                .selected_text(label(options.delimiter))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut options.delimiter, None, "Detect");
+                    for (delimiter, label) in DELIMITER_CHOICES {
+                        ui.selectable_value(&mut options.delimiter, Some(delimiter), label);
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Quote");
+            let selected = QUOTE_CHOICES
+                .iter()
+                .find(|(quote, _)| *quote == options.quote)
+                .map_or("Other", |(_, label)| label);
+            egui::ComboBox::from_id_salt("csv-quote")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for (quote, label) in QUOTE_CHOICES {
+                        ui.selectable_value(&mut options.quote, quote, label);
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Encoding");
+            egui::ComboBox::from_id_salt("csv-encoding")
+                .selected_text(options.encoding.map_or("Detect", Encoding::name))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut options.encoding, None, "Detect");
+                    for encoding in ENCODING_CHOICES {
+                        ui.selectable_value(&mut options.encoding, Some(encoding), encoding.name());
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Header row");
+            let label = |has_header: Option<bool>| match has_header {
+                None => "Detect",
+                Some(true) => "First row",
+                Some(false) => "None",
+            };
+            egui::ComboBox::from_id_salt("csv-header")
+                .selected_text(label(options.has_header))
+                .show_ui(ui, |ui| {
+                    for has_header in [None, Some(true), Some(false)] {
+                        ui.selectable_value(&mut options.has_header, has_header, label(has_header));
+                    }
+                });
+            ui.end_row();
+        });
+    });
+    *options != before
+}
+
+/// Edits where a sheet's header sits; unchecked values are detected when the
+/// workbook loads. Returns whether the layout changed.
+fn header_layout_editor(ui: &mut egui::Ui, layout: &mut HeaderLayout) -> bool {
+    ui.horizontal(|ui| {
+        let skip_changed = optional_count(ui, "Skip rows", &mut layout.skip_rows, 0);
+        let header_changed = optional_count(ui, "Header rows", &mut layout.header_rows, 1);
+        skip_changed || header_changed
+    })
+    .inner
+}
+
+fn optional_count(ui: &mut egui::Ui, label: &str, value: &mut Option<usize>, default: usize) -> bool {
+    let mut enabled = value.is_some();
+    let mut changed = ui.checkbox(&mut enabled, label).changed();
+    if changed {
+        *value = enabled.then_some(default);
+    }
+    if let Some(count) = value {
+        changed |= ui.add(egui::DragValue::new(count).range(0..=100)).changed();
+    }
+    changed
+}
+
+fn optional_number(ui: &mut egui::Ui, label: &str, value: &mut Option<f64>) {
+    let mut enabled = value.is_some();
+    if ui.checkbox(&mut enabled, label).changed() {
+        *value = enabled.then_some(0.0);
+    }
+    if let Some(number) = value {
+        ui.add(egui::DragValue::new(number).speed(1.0));
+    }
+}
+
+/// Edits an optional date as `YYYY-MM-DD` text, keeping partially typed input
+/// in egui's temporary storage until it parses.
+fn optional_date(ui: &mut egui::Ui, label: &str, value: &mut Option<NaiveDate>) {
+    let id = ui.id().with(label);
+    let mut text = ui.data_mut(|data| {
+        data.get_temp::<String>(id).unwrap_or_else(|| {
+            value
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default()
+        })
+    });
+    ui.label(label);
+    let response = ui.add(
+        egui::TextEdit::singleline(&mut text)
+            .hint_text("YYYY-MM-DD")
+            .desired_width(80.0),
+    );
+    if response.changed() {
+        if text.trim().is_empty() {
+            *value = None;
+        } else if let Ok(date) = NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d") {
+            *value = Some(date);
+        }
+    }
+    ui.data_mut(|data| data.insert_temp(id, text));
 }
 
 fn render_table(ui: &mut egui::Ui, result: &QueryResult) {
-    if result.headers.is_empty() {
+    if result.fields.is_empty() {
         ui.label("No data loaded.");
         return;
     }
 
     egui::ScrollArea::both().show(ui, |ui| {
         egui::Grid::new("table-grid").striped(true).show(ui, |ui| {
-            for header in &result.headers {
+            for header in result.headers() {
                 ui.label(RichText::new(header).strong());
             }
             ui.end_row();
 
             for row in &result.rows {
-                for cell in row {
-                    ui.label(cell);
+                for (cell, field) in row.iter().zip(&result.fields) {
+                    if matches!(field.data_type, DataType::Number | DataType::Duration) {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.label(cell.display_string());
+                        });
+                    } else {
+                        ui.label(cell.display_string());
+                    }
                 }
                 ui.end_row();
             }
@@ -256,19 +1476,35 @@ fn render_table(ui: &mut egui::Ui, result: &QueryResult) {
     });
 }
 
+/// Pairs each row's dimension label with its first measure, skipping rows
+/// where that measure is not numeric.
+fn labelled_values(result: &QueryResult) -> Option<Vec<(String, f64)>> {
+    let measure = *result.measure_indices().first()?;
+    if result.rows.is_empty() || result.dimension_indices().is_empty() {
+        return None;
+    }
+    Some(
+        result
+            .rows
+            .iter()
+            .filter_map(|row| {
+                let value = row.get(measure)?.as_f64()?;
+                Some((result.row_label(row), value))
+            })
+            .collect(),
+    )
+}
+
 fn render_bar(ui: &mut egui::Ui, result: &QueryResult) {
-    if result.rows.is_empty() || result.headers.len() < 2 {
+    let Some(values) = labelled_values(result) else {
         ui.label("Add a dimension in Rows and a measure in Columns.");
         return;
-    }
+    };
 
-    let bars = result
-        .rows
-        .iter()
+    let bars = values
+        .into_iter()
         .enumerate()
-        .filter_map(|(idx, row)| row.get(1).and_then(|value| value.parse::<f64>().ok()).map(|y| {
-            Bar::new(idx as f64, y).name(row[0].clone())
-        }))
+        .map(|(idx, (label, y))| Bar::new(idx as f64, y).name(label))
         .collect::<Vec<_>>();
 
     Plot::new("bar-chart")
@@ -279,18 +1515,15 @@ fn render_bar(ui: &mut egui::Ui, result: &QueryResult) {
 }
 
 fn render_line(ui: &mut egui::Ui, result: &QueryResult) {
-    if result.rows.is_empty() || result.headers.len() < 2 {
+    let Some(values) = labelled_values(result) else {
         ui.label("Add a dimension in Rows and a measure in Columns.");
         return;
-    }
+    };
 
-    let points = result
-        .rows
-        .iter()
+    let points = values
+        .into_iter()
         .enumerate()
-        .filter_map(|(idx, row)| row.get(1).and_then(|value| value.parse::<f64>().ok()).map(|y| {
-            [idx as f64, y]
-        }))
+        .map(|(idx, (_, y))| [idx as f64, y])
         .collect::<Vec<_>>();
 
     Plot::new("line-chart").show(ui, |plot_ui| {
@@ -299,23 +1532,22 @@ fn render_line(ui: &mut egui::Ui, result: &QueryResult) {
 }
 
 fn render_pie(ui: &mut egui::Ui, result: &QueryResult) {
-    if result.rows.is_empty() || result.headers.len() < 2 {
+    let Some(values) = labelled_values(result) else {
         ui.label("Add a dimension in Rows and a measure in Columns.");
         return;
-    }
+    };
 
     ui.vertical(|ui| {
         ui.label("Pie chart preview (values listed):");
-        for row in &result.rows {
-            if let Some(value) = row.get(1) {
-                ui.label(format!("{}: {}", row.get(0).unwrap_or(&"".to_string()), value));
-            }
+        for (label, value) in values {
+            ui.label(format!("{label}: {value}"));
         }
     });
 }
 
 fn render_scatter(ui: &mut egui::Ui, result: &QueryResult) {
-    if result.rows.is_empty() || result.headers.len() < 3 {
+    let measures = result.measure_indices();
+    if result.rows.is_empty() || measures.len() < 2 {
         ui.label("Add a dimension in Rows and two measures in Columns.");
         return;
     }
@@ -324,8 +1556,8 @@ fn render_scatter(ui: &mut egui::Ui, result: &QueryResult) {
         .rows
         .iter()
         .filter_map(|row| {
-            let x = row.get(1)?.parse::<f64>().ok()?;
-            let y = row.get(2)?.parse::<f64>().ok()?;
+            let x = row.get(measures[0])?.as_f64()?;
+            let y = row.get(measures[1])?.as_f64()?;
             Some([x, y])
         })
         .collect::<Vec<_>>();
@@ -334,3 +1566,37 @@ fn render_scatter(ui: &mut egui::Ui, result: &QueryResult) {
         plot_ui.points(Points::new(PlotPoints::from_iter(points)));
     });
 }
+
+/// Draws one bar per bin of the first binned dimension, spanning the bin's
+/// edges.
+fn render_histogram(ui: &mut egui::Ui, result: &QueryResult) {
+    let binned = result
+        .fields
+        .iter()
+        .enumerate()
+        .find_map(|(index, field)| Some((index, field.bins.as_ref()?)));
+    let measure = result.measure_indices().first().copied();
+    let (Some((dimension, bins)), Some(measure)) = (binned, measure) else {
+        ui.label("Add a binned Number dimension in Rows and a measure in Columns.");
+        return;
+    };
+
+    let bars = result
+        .rows
+        .iter()
+        .filter_map(|row| {
+            let lower = row.get(dimension)?.as_f64()?;
+            let upper = bins.upper_edge(lower)?;
+            let value = row.get(measure)?.as_f64()?;
+            Some(
+                Bar::new((lower + upper) / 2.0, value)
+                    .width(upper - lower)
+                    .name(format!("{lower} – {upper}")),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    Plot::new("histogram").show(ui, |plot_ui| {
+        plot_ui.bar_chart(BarChart::new(bars));
+    });
+}
@@ -7,15 +7,17 @@ pub enum ChartType {
     Pie,
     Table,
     Scatter,
+    Histogram,
 }
 
 impl ChartType {
-    pub const ALL: [ChartType; 5] = [
+    pub const ALL: [ChartType; 6] = [
         ChartType::Bar,
         ChartType::Line,
         ChartType::Pie,
         ChartType::Table,
         ChartType::Scatter,
+        ChartType::Histogram,
     ];
 
     pub fn label(self) -> &'static str {
@@ -25,6 +27,7 @@ impl ChartType {
             ChartType::Pie => "Pie",
             ChartType::Table => "Table",
             ChartType::Scatter => "Scatter",
+            ChartType::Histogram => "Histogram",
         }
     }
 }
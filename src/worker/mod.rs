@@ -0,0 +1,119 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Rows a long-running worker handles between progress reports, which is
+/// also how often it notices that its task was cancelled.
+const CHECK_ROWS: usize = 65_536;
+
+/// Shared between a task and its worker thread: the worker reports how far
+/// it got and checks whether the task was cancelled.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    cancelled: Arc<AtomicBool>,
+    /// Fraction done, stored as `f32` bits.
+    fraction: Arc<AtomicU32>,
+    /// The share of the task this handle reports on; see `part`.
+    start: f32,
+    span: f32,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            cancelled: Arc::default(),
+            fraction: Arc::default(),
+            start: 0.0,
+            span: 1.0,
+        }
+    }
+}
+
+impl Progress {
+    /// Records `fraction` (0 to 1) and returns whether to keep going, which
+    /// is the shape of the query engine's progress callback.
+    pub fn report(&self, fraction: f32) -> bool {
+        let fraction = self.start + fraction.clamp(0.0, 1.0) * self.span;
+        self.fraction.store(fraction.to_bits(), Ordering::Relaxed);
+        !self.is_cancelled()
+    }
+
+    /// A handle for one step of the task, which reports its own 0 to 1 as
+    /// `start` to `end` of this handle's range.
+    pub fn part(&self, start: f32, end: f32) -> Progress {
+        Progress {
+            start: self.start + start * self.span,
+            span: (end - start) * self.span,
+            ..self.clone()
+        }
+    }
+
+    /// Reports `row` of `rows` done every `CHECK_ROWS` rows and returns
+    /// whether to keep going.
+    pub fn report_rows(&self, row: usize, rows: usize) -> bool {
+        !row.is_multiple_of(CHECK_ROWS) || self.report(row as f32 / rows.max(1) as f32)
+    }
+
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.fraction.load(Ordering::Relaxed))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub enum TaskStatus<T> {
+    Running,
+    Finished(T),
+    /// The worker thread panicked before producing a result.
+    Failed,
+}
+
+/// Work running on a background thread, polled from the UI. Dropping the
+/// task cancels it: the worker is told to stop and its result is discarded.
+pub struct Task<T> {
+    receiver: Receiver<T>,
+    progress: Progress,
+}
+
+impl<T: Send + 'static> Task<T> {
+    /// Runs `work` on a new thread and calls `notify` once it has a result,
+    /// so the UI can repaint and poll.
+    pub fn spawn(
+        notify: impl Fn() + Send + 'static,
+        work: impl FnOnce(&Progress) -> T + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let progress = Progress::default();
+        let worker_progress = progress.clone();
+        thread::spawn(move || {
+            let result = work(&worker_progress);
+            if !worker_progress.is_cancelled() && sender.send(result).is_ok() {
+                notify();
+            }
+        });
+        Self { receiver, progress }
+    }
+}
+
+impl<T> Task<T> {
+    pub fn poll(&self) -> TaskStatus<T> {
+        match self.receiver.try_recv() {
+            Ok(result) => TaskStatus::Finished(result),
+            Err(TryRecvError::Empty) => TaskStatus::Running,
+            Err(TryRecvError::Disconnected) => TaskStatus::Failed,
+        }
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.progress.fraction()
+    }
+}
+
+impl<T> Drop for Task<T> {
+    fn drop(&mut self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+}